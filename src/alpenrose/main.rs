@@ -0,0 +1,271 @@
+use aspenlib::dmfr::AuthorizationType;
+use aspenlib::feed_authorization::{self, FeedAuthorization, FeedKind, FeedSecret};
+use aspenlib::AspenRpcClient;
+use clap::Parser;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tarpc::{client, context, tokio_serde::formats::Json};
+use tokio_postgres::NoTls;
+
+#[derive(Parser)]
+struct Flags {
+    /// Postgres connection string, the realtime feed list is read from gtfs.realtime_feeds.
+    #[clap(long)]
+    postgres: String,
+    /// Address aspen is listening on.
+    #[clap(long, default_value = "[::1]:40427")]
+    aspen: SocketAddr,
+    /// Seconds between each poll of a feed.
+    #[clap(long, default_value_t = 30)]
+    interval: u64,
+    /// How many feeds are fetched at the same time.
+    #[clap(long, default_value_t = 32)]
+    threads: usize,
+    /// Json file of feed id -> secret, for feeds that declare an authorization.
+    #[clap(long, default_value = "secrets.json")]
+    secrets: String,
+    /// Only poll this realtime feed.
+    #[clap(long)]
+    limittorealtimefeed: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct RealtimeFeedToFetch {
+    feed_id: String,
+    vehicles_url: Option<String>,
+    trips_url: Option<String>,
+    alerts_url: Option<String>,
+    authorization: Option<FeedAuthorization>,
+}
+
+//the feed list only changes when the importer runs
+const FEED_LIST_REFRESH: Duration = Duration::from_secs(600);
+
+async fn get_realtime_feeds(
+    postgres_client: &tokio_postgres::Client,
+    limittorealtimefeed: &Option<String>,
+) -> Result<Vec<RealtimeFeedToFetch>, tokio_postgres::Error> {
+    let rows = postgres_client
+        .query(
+            "SELECT onestop_feed_id, realtime_vehicle_positions, realtime_trip_updates, realtime_alerts,
+            authorization_type, authorization_param_name FROM gtfs.realtime_feeds
+            WHERE ($1::text IS NULL OR onestop_feed_id = $1)
+            AND (realtime_vehicle_positions IS NOT NULL OR realtime_trip_updates IS NOT NULL OR realtime_alerts IS NOT NULL);",
+            &[limittorealtimefeed],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let feed_id: String = row.get(0);
+            let authorization_type: Option<String> = row.get(4);
+
+            //fetching without the authorization the feed asks for would only get rejected
+            let authorization = match authorization_type {
+                Some(type_) => match type_.parse::<AuthorizationType>() {
+                    Ok(type_) => Some(FeedAuthorization {
+                        type_: type_,
+                        param_name: row.get(5),
+                    }),
+                    Err(_) => {
+                        eprintln!("Skipping {}, unknown authorization type {}", feed_id, type_);
+                        return None;
+                    }
+                },
+                None => None,
+            };
+
+            Some(RealtimeFeedToFetch {
+                feed_id: feed_id,
+                vehicles_url: row.get(1),
+                trips_url: row.get(2),
+                alerts_url: row.get(3),
+                authorization: authorization,
+            })
+        })
+        .collect())
+}
+
+async fn fetch_url(
+    client: &reqwest::Client,
+    feed: &RealtimeFeedToFetch,
+    kind: FeedKind,
+    url: &Option<String>,
+    secret: &Option<FeedSecret>,
+) -> Option<Vec<u8>> {
+    let url = url.as_ref()?;
+
+    let request = match (&feed.authorization, secret) {
+        (Some(authorization), Some(secret)) => {
+            match feed_authorization::authorized_request(client, url, authorization, kind, secret) {
+                Ok(request) => request,
+                Err(err) => {
+                    eprintln!(
+                        "Could not authorize {} {}: {}",
+                        feed.feed_id,
+                        kind.as_str(),
+                        err
+                    );
+                    return None;
+                }
+            }
+        }
+        _ => client.get(url),
+    };
+
+    match request.send().await {
+        Ok(response) => match response.status().is_success() {
+            true => match response.bytes().await {
+                Ok(bytes) => Some(bytes.to_vec()),
+                Err(err) => {
+                    eprintln!("Error reading {} for {}: {}", url, feed.feed_id, err);
+                    None
+                }
+            },
+            false => {
+                eprintln!(
+                    "{} returned {} for {}",
+                    url,
+                    response.status(),
+                    feed.feed_id
+                );
+                None
+            }
+        },
+        Err(err) => {
+            eprintln!("Error fetching {} for {}: {}", url, feed.feed_id, err);
+            None
+        }
+    }
+}
+
+async fn poll_feed(
+    http_client: &reqwest::Client,
+    aspen_client: &AspenRpcClient,
+    feed: &RealtimeFeedToFetch,
+    secrets: &HashMap<String, FeedSecret>,
+) {
+    let secret = feed_authorization::secret_for_feed(secrets, &feed.feed_id);
+
+    if feed.authorization.is_some() && secret.is_none() {
+        println!(
+            "Skipping {}, it needs a secret in {} or the secrets file",
+            feed.feed_id,
+            feed_authorization::secret_env_var_name(&feed.feed_id)
+        );
+        return;
+    }
+
+    let (vehicles, trips, alerts) = futures::join!(
+        fetch_url(
+            http_client,
+            feed,
+            FeedKind::Vehicles,
+            &feed.vehicles_url,
+            &secret
+        ),
+        fetch_url(http_client, feed, FeedKind::Trips, &feed.trips_url, &secret),
+        fetch_url(
+            http_client,
+            feed,
+            FeedKind::Alerts,
+            &feed.alerts_url,
+            &secret
+        )
+    );
+
+    if vehicles.is_none() && trips.is_none() && alerts.is_none() {
+        return;
+    }
+
+    match aspen_client
+        .new_rt_kactus(
+            context::current(),
+            feed.feed_id.to_owned(),
+            vehicles,
+            trips,
+            alerts,
+        )
+        .await
+    {
+        Ok(Ok(())) => {}
+        Ok(Err(decode_errors)) => {
+            eprintln!(
+                "Aspen could not decode {}: {:?}",
+                feed.feed_id, decode_errors
+            );
+        }
+        Err(err) => {
+            eprintln!("Could not send {} to aspen: {:?}", feed.feed_id, err);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let flags = Flags::parse();
+
+    let (postgres_client, connection) = tokio_postgres::connect(&flags.postgres, NoTls).await?;
+
+    // The connection object performs the actual communication with the database,
+    // so spawn it off to run on its own.
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+
+    let mut transport = tarpc::serde_transport::tcp::connect(flags.aspen, Json::default);
+    transport.config_mut().max_frame_length(usize::MAX);
+    let aspen_client = AspenRpcClient::new(client::Config::default(), transport.await?).spawn();
+
+    let http_client = reqwest::ClientBuilder::new()
+        .deflate(true)
+        .gzip(true)
+        .brotli(true)
+        .timeout(Duration::from_secs(20))
+        .build()
+        .unwrap();
+
+    let secrets = feed_authorization::load_secrets(&flags.secrets);
+
+    let mut feeds = get_realtime_feeds(&postgres_client, &flags.limittorealtimefeed).await?;
+    let mut feeds_loaded_at = Instant::now();
+    println!("Polling {} realtime feeds", feeds.len());
+
+    let mut interval = tokio::time::interval(Duration::from_secs(flags.interval));
+
+    loop {
+        interval.tick().await;
+
+        if feeds_loaded_at.elapsed() > FEED_LIST_REFRESH {
+            match get_realtime_feeds(&postgres_client, &flags.limittorealtimefeed).await {
+                Ok(new_feeds) => {
+                    feeds = new_feeds;
+                    feeds_loaded_at = Instant::now();
+                }
+                Err(err) => eprintln!("Could not refresh the realtime feed list: {}", err),
+            }
+        }
+
+        let start = Instant::now();
+
+        futures::stream::iter(
+            feeds
+                .iter()
+                .map(|feed| poll_feed(&http_client, &aspen_client, feed, &secrets)),
+        )
+        .buffer_unordered(flags.threads)
+        .collect::<Vec<()>>()
+        .await;
+
+        println!(
+            "Polled {} feeds in {}ms",
+            feeds.len(),
+            start.elapsed().as_millis()
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::dmfr::AuthorizationType;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+//authorization declared by a feed in the DMFR
+#[derive(Clone, Debug)]
+pub struct FeedAuthorization {
+    pub type_: AuthorizationType,
+    pub param_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FeedKind {
+    //the static zip, fetched by transitlanddownload
+    Schedule,
+    Vehicles,
+    Trips,
+    Alerts,
+}
+
+impl FeedKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedKind::Schedule => "schedule",
+            FeedKind::Vehicles => "vehicles",
+            FeedKind::Trips => "trips",
+            FeedKind::Alerts => "alerts",
+        }
+    }
+}
+
+//either one secret for every url of the feed, or one per kind
+//replace_url feeds need the per kind form, each kind is fetched from a different url
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum FeedSecret {
+    Single(String),
+    PerKind {
+        schedule: Option<String>,
+        vehicles: Option<String>,
+        trips: Option<String>,
+        alerts: Option<String>,
+    },
+}
+
+impl FeedSecret {
+    pub fn for_kind(&self, kind: FeedKind) -> Option<&str> {
+        match self {
+            FeedSecret::Single(secret) => Some(secret.as_str()),
+            FeedSecret::PerKind {
+                schedule,
+                vehicles,
+                trips,
+                alerts,
+            } => match kind {
+                FeedKind::Schedule => schedule.as_deref(),
+                FeedKind::Vehicles => vehicles.as_deref(),
+                FeedKind::Trips => trips.as_deref(),
+                FeedKind::Alerts => alerts.as_deref(),
+            },
+        }
+    }
+}
+
+//secrets file is a json object of feed id -> secret, eg {"f-dp3-metra": "username:password"}
+//for basic_auth the secret is "username:password"
+//for replace_url it is the urls, eg {"f-feed~rt": {"vehicles": "https://...", "trips": "https://...", "alerts": "https://..."}}
+pub fn load_secrets(path: &str) -> HashMap<String, FeedSecret> {
+    match fs::read_to_string(path) {
+        Ok(contents) => match serde_json::from_str::<HashMap<String, FeedSecret>>(&contents) {
+            Ok(secrets) => secrets,
+            Err(err) => {
+                eprintln!("Could not parse secrets file {}: {}", path, err);
+                HashMap::new()
+            }
+        },
+        Err(_) => HashMap::new(),
+    }
+}
+
+//CATENARY_SECRET_F_DP3_METRA for f-dp3-metra
+pub fn secret_env_var_name(feed_id: &str) -> String {
+    let normalized = feed_id
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect::<String>();
+
+    format!("CATENARY_SECRET_{}", normalized)
+}
+
+//environment variables win over the secrets file, they hold the secret or the same json object as the file
+pub fn secret_for_feed(secrets: &HashMap<String, FeedSecret>, feed_id: &str) -> Option<FeedSecret> {
+    match std::env::var(secret_env_var_name(feed_id)) {
+        Ok(secret) => Some(match secret.trim_start().starts_with('{') {
+            true => {
+                serde_json::from_str::<FeedSecret>(&secret).unwrap_or(FeedSecret::Single(secret))
+            }
+            false => FeedSecret::Single(secret),
+        }),
+        Err(_) => secrets.get(feed_id).map(|secret| secret.to_owned()),
+    }
+}
+
+//builds the request with the feed's authorization applied
+//returns an error message when the authorization can't be applied with the secret given
+pub fn authorized_request(
+    client: &reqwest::Client,
+    url: &str,
+    authorization: &FeedAuthorization,
+    kind: FeedKind,
+    secret: &FeedSecret,
+) -> Result<reqwest::RequestBuilder, String> {
+    let secret = match (authorization.type_, secret) {
+        //a single url can't stand in for the vehicles, trips and alerts urls at once
+        (AuthorizationType::ReplaceUrl, FeedSecret::Single(_)) => {
+            return Err(String::from(
+                "replace_url secrets need a url per kind, {\"vehicles\": ..., \"trips\": ..., \"alerts\": ...}",
+            ))
+        }
+        (_, secret) => match secret.for_kind(kind) {
+            Some(secret) => secret,
+            None => return Err(format!("secret has no {} entry", kind.as_str())),
+        },
+    };
+
+    match authorization.type_ {
+        AuthorizationType::Header => match &authorization.param_name {
+            Some(header_name) => Ok(client.get(url).header(header_name.as_str(), secret)),
+            None => Err(String::from("header authorization without a param_name")),
+        },
+        AuthorizationType::BasicAuth => match secret.split_once(':') {
+            Some((username, password)) => Ok(client.get(url).basic_auth(username, Some(password))),
+            None => Err(String::from(
+                "basic_auth secrets need to be written as username:password",
+            )),
+        },
+        AuthorizationType::QueryParam => match &authorization.param_name {
+            Some(param_name) => match reqwest::Url::parse(url) {
+                Ok(mut parsed_url) => {
+                    parsed_url
+                        .query_pairs_mut()
+                        .append_pair(param_name.as_str(), secret);
+                    Ok(client.get(parsed_url))
+                }
+                Err(err) => Err(format!("invalid url {}: {}", url, err)),
+            },
+            None => Err(String::from(
+                "query_param authorization without a param_name",
+            )),
+        },
+        //Transitland Extended URLs mark the path segment holding the key with {}
+        AuthorizationType::PathSegment => match url.contains("{}") {
+            true => Ok(client.get(url.replace("{}", secret))),
+            false => Err(String::from(
+                "path_segment authorization but the url has no {}",
+            )),
+        },
+        //the secret is the url to fetch instead
+        AuthorizationType::ReplaceUrl => Ok(client.get(secret)),
+    }
+}
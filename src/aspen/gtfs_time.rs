@@ -0,0 +1,154 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+/// Parses an IANA timezone name as found in agency.txt and stops.txt.
+pub fn parse_timezone(name: Option<&str>) -> Option<Tz> {
+    name.and_then(|name| name.trim().parse::<Tz>().ok())
+}
+
+/// The instant GTFS times on `service_date` are counted from.
+///
+/// GTFS measures stop times from "noon minus 12h" of the service day, not from midnight,
+/// so on the days clocks change a 08:00:00 stop time is still 08:00 on the wall clock.
+pub fn service_day_origin(timezone: &Tz, service_date: NaiveDate) -> Option<DateTime<Tz>> {
+    //noon is never skipped or repeated by a DST switch
+    let noon = timezone
+        .from_local_datetime(&service_date.and_hms_opt(12, 0, 0)?)
+        .single()?;
+
+    Some(noon - Duration::hours(12))
+}
+
+/// Converts seconds since the start of a service day into a UTC instant.
+/// Times past 24:00:00 land on the following calendar day.
+pub fn gtfs_time_to_utc(
+    timezone: &Tz,
+    service_date: NaiveDate,
+    seconds: i64,
+) -> Option<DateTime<Utc>> {
+    service_day_origin(timezone, service_date)
+        .map(|origin| (origin + Duration::seconds(seconds)).with_timezone(&Utc))
+}
+
+/// The inverse of `gtfs_time_to_utc`, seconds of `instant` counted from the start of `service_date`.
+pub fn utc_to_gtfs_time(
+    timezone: &Tz,
+    service_date: NaiveDate,
+    instant: DateTime<Utc>,
+) -> Option<i64> {
+    service_day_origin(timezone, service_date)
+        .map(|origin| (instant - origin.with_timezone(&Utc)).num_seconds())
+}
+
+/// Where a departure board for `service_date` starts when no time is asked for.
+///
+/// On today's service date that is now, counted from the start of the service day.
+/// Any other date starts at the same wall clock time as now.
+pub fn default_start_time(timezone: &Tz, service_date: NaiveDate, now: DateTime<Utc>) -> i64 {
+    let local_now = now.with_timezone(timezone);
+    let wall_clock_seconds = local_now.num_seconds_from_midnight() as i64;
+
+    match local_now.date_naive() == service_date {
+        true => utc_to_gtfs_time(timezone, service_date, now).unwrap_or(wall_clock_seconds),
+        false => wall_clock_seconds,
+    }
+}
+
+/// Parses the YYYYMMDD start_date of a GTFS-RT TripDescriptor.
+pub fn parse_gtfs_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y%m%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_york() -> Tz {
+        parse_timezone(Some("America/New_York")).unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn spring_forward_keeps_wall_clock_time() {
+        let service_date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+
+        //08:00 EDT, the day started in EST
+        assert_eq!(
+            gtfs_time_to_utc(&new_york(), service_date, 8 * 3600),
+            Some(utc(2024, 3, 10, 12, 0))
+        );
+        assert_eq!(
+            utc_to_gtfs_time(&new_york(), service_date, utc(2024, 3, 10, 12, 0)),
+            Some(8 * 3600)
+        );
+    }
+
+    #[test]
+    fn fall_back_keeps_wall_clock_time() {
+        let service_date = NaiveDate::from_ymd_opt(2024, 11, 3).unwrap();
+
+        //08:00 EST, the day started in EDT
+        assert_eq!(
+            gtfs_time_to_utc(&new_york(), service_date, 8 * 3600),
+            Some(utc(2024, 11, 3, 13, 0))
+        );
+        assert_eq!(
+            utc_to_gtfs_time(&new_york(), service_date, utc(2024, 11, 3, 13, 0)),
+            Some(8 * 3600)
+        );
+    }
+
+    #[test]
+    fn times_past_midnight_land_on_the_next_day() {
+        let service_date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+
+        //25:30:00 is 01:30 EDT on the 11th
+        assert_eq!(
+            gtfs_time_to_utc(&new_york(), service_date, 25 * 3600 + 30 * 60),
+            Some(utc(2024, 3, 11, 5, 30))
+        );
+    }
+
+    #[test]
+    fn default_start_time_uses_now_on_todays_service_date() {
+        let service_date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+
+        //08:00 EDT on the day clocks went forward
+        assert_eq!(
+            default_start_time(&new_york(), service_date, utc(2024, 3, 10, 12, 0)),
+            8 * 3600
+        );
+    }
+
+    #[test]
+    fn default_start_time_uses_wall_clock_time_on_other_dates() {
+        let service_date = NaiveDate::from_ymd_opt(2024, 3, 12).unwrap();
+
+        //it's 08:00 EDT on the 10th, counting from the 12th would give a negative time
+        assert_eq!(
+            default_start_time(&new_york(), service_date, utc(2024, 3, 10, 12, 0)),
+            8 * 3600
+        );
+
+        let service_date = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+
+        //counting from the 9th would give 31:00:00, past every departure that day
+        assert_eq!(
+            default_start_time(&new_york(), service_date, utc(2024, 3, 10, 12, 0)),
+            8 * 3600
+        );
+    }
+
+    #[test]
+    fn parses_realtime_start_dates() {
+        assert_eq!(
+            parse_gtfs_date("20241103"),
+            NaiveDate::from_ymd_opt(2024, 11, 3)
+        );
+        assert_eq!(parse_gtfs_date("2024-11-03"), None);
+    }
+}
@@ -1,36 +1,107 @@
-const MAKE_VEHICLES_FEED_LIST: [&str; 9] = [
-    "f-mta~nyc~rt~subway~1~2~3~4~5~6~7",
-    "f-mta~nyc~rt~subway~a~c~e",
-    "f-mta~nyc~rt~subway~b~d~f~m",
-    "f-mta~nyc~rt~subway~g",
-    "f-mta~nyc~rt~subway~j~z",
-    "f-mta~nyc~rt~subway~l",
-    "f-mta~nyc~rt~subway~n~q~r~w",
-    "f-mta~nyc~rt~subway~sir",
-    "f-bart~rt",
+use crate::rail_location_interpolation::{self, PostgresPool, StaticRailCache};
+use crate::realtime_store::{FeedData, RealtimeStore};
+use aspenlib::RtDecodeErrors;
+
+//(realtime feed, static feed, trip id prefix) for feeds that publish trip updates but no usable vehicle positions
+//the importer skips BART's own static feed for the 511 regional one, which puts the agency id in front of every trip id
+const MAKE_VEHICLES_FEED_LIST: [(&str, &str, Option<&str>); 9] = [
+    (
+        "f-mta~nyc~rt~subway~1~2~3~4~5~6~7",
+        "f-dr5r-nyctsubway",
+        None,
+    ),
+    ("f-mta~nyc~rt~subway~a~c~e", "f-dr5r-nyctsubway", None),
+    ("f-mta~nyc~rt~subway~b~d~f~m", "f-dr5r-nyctsubway", None),
+    ("f-mta~nyc~rt~subway~g", "f-dr5r-nyctsubway", None),
+    ("f-mta~nyc~rt~subway~j~z", "f-dr5r-nyctsubway", None),
+    ("f-mta~nyc~rt~subway~l", "f-dr5r-nyctsubway", None),
+    ("f-mta~nyc~rt~subway~n~q~r~w", "f-dr5r-nyctsubway", None),
+    ("f-mta~nyc~rt~subway~sir", "f-dr5r-nyctsubway", None),
+    ("f-bart~rt", "f-sf~bay~area~rg", Some("BA:")),
 ];
 
+//None if nothing was sent, otherwise the decoded feed or the decode error
+fn decode_payload(payload: &Option<Vec<u8>>) -> Option<Result<gtfs_rt::FeedMessage, String>> {
+    payload.as_ref().map(|bytes| {
+        kactus::parse_protobuf_message(bytes.as_slice()).map_err(|err| format!("{:?}", err))
+    })
+}
+
 pub async fn new_rt_kactus(
+    store: &RealtimeStore,
+    pool: &Option<PostgresPool>,
+    static_rail_cache: &StaticRailCache,
     realtime_feed_id: String,
     vehicles: Option<Vec<u8>>,
     trips: Option<Vec<u8>>,
     alerts: Option<Vec<u8>>,
-) -> bool {
+) -> Result<(), RtDecodeErrors> {
     //decode Vec<u8> into gtfs_rt::FeedMessage
+    let decoded_vehicles = decode_payload(&vehicles);
+    let decoded_trips = decode_payload(&trips);
+    let decoded_alerts = decode_payload(&alerts);
+
+    let mut errors = RtDecodeErrors::default();
 
-    if (vehicles.is_some()) {
-        let decoded_gtfs_vehicles =
-            kactus::parse_protobuf_message(vehicles.as_ref().unwrap().as_slice());
+    //make a vehicles feed out of the trip updates
+    let mut synthetic_vehicles: Option<gtfs_rt::FeedMessage> = None;
 
-        if decoded_gtfs_vehicles.is_ok() {
-            if MAKE_VEHICLES_FEED_LIST.contains(&realtime_feed_id.as_str()) {
-                //make a vehicles feed
+    if let (Some(Ok(trips_message)), Some(pool)) = (&decoded_trips, pool) {
+        let static_feed = MAKE_VEHICLES_FEED_LIST
+            .iter()
+            .find(|(realtime_id, _, _)| *realtime_id == realtime_feed_id.as_str());
 
-                //get gtfs data from postgres?
+        if let Some((_, static_feed_id, trip_id_prefix)) = static_feed {
+            if let Some(static_data) = rail_location_interpolation::get_static_rail_data(
+                pool,
+                static_rail_cache,
+                static_feed_id,
+            )
+            .await
+            {
+                synthetic_vehicles = Some(rail_location_interpolation::make_new_vehicle_feed(
+                    &realtime_feed_id,
+                    &static_data,
+                    *trip_id_prefix,
+                    trips_message,
+                ));
             }
-        } else {
         }
     }
 
-    true
+    let mut store = store.write().await;
+    let feed_store = store.entry(realtime_feed_id.to_owned()).or_default();
+
+    match decoded_vehicles {
+        Some(Ok(message)) => feed_store.vehicles = Some(FeedData::new(message)),
+        Some(Err(err)) => errors.vehicles = Some(err),
+        None => {}
+    }
+
+    //the synthetic feed replaces whatever the agency sent, their positions are the reason it exists
+    if let Some(message) = synthetic_vehicles {
+        feed_store.vehicles = Some(FeedData::new(message));
+    }
+
+    match decoded_trips {
+        Some(Ok(message)) => feed_store.trips = Some(FeedData::new(message)),
+        Some(Err(err)) => errors.trips = Some(err),
+        None => {}
+    }
+
+    match decoded_alerts {
+        Some(Ok(message)) => feed_store.alerts = Some(FeedData::new(message)),
+        Some(Err(err)) => errors.alerts = Some(err),
+        None => {}
+    }
+
+    if errors.vehicles.is_some() || errors.trips.is_some() || errors.alerts.is_some() {
+        println!(
+            "Could not decode realtime data for {}: {:?}",
+            realtime_feed_id, errors
+        );
+        Err(errors)
+    } else {
+        Ok(())
+    }
 }
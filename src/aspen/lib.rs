@@ -1,6 +1,103 @@
+use serde::{Deserialize, Serialize};
 use std::env;
 use tracing_subscriber::{fmt::format::FmtSpan, prelude::*};
 
+pub mod dmfr;
+pub mod feed_authorization;
+pub mod gtfs_time;
+
+/// Payloads sent to new_rt_kactus that could not be decoded as a GTFS-RT FeedMessage.
+/// Payloads that did decode are still stored.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RtDecodeErrors {
+    pub vehicles: Option<String>,
+    pub trips: Option<String>,
+    pub alerts: Option<String>,
+}
+
+/// A vehicle position from a realtime feed, flattened for clients.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VehiclePositionSummary {
+    pub realtime_feed_id: String,
+    pub entity_id: String,
+    pub trip_id: Option<String>,
+    pub route_id: Option<String>,
+    pub vehicle_id: Option<String>,
+    pub vehicle_label: Option<String>,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub bearing: Option<f32>,
+    pub speed: Option<f32>,
+    pub current_stop_sequence: Option<u32>,
+    pub stop_id: Option<String>,
+    pub timestamp: Option<u64>,
+}
+
+/// Predicted arrival and departure at one stop of a trip update.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StopTimeUpdateSummary {
+    pub stop_sequence: Option<u32>,
+    pub stop_id: Option<String>,
+    pub arrival_time: Option<i64>,
+    pub arrival_delay: Option<i32>,
+    pub departure_time: Option<i64>,
+    pub departure_delay: Option<i32>,
+    pub schedule_relationship: Option<i32>,
+}
+
+/// A trip update from a realtime feed, flattened for clients.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TripUpdateSummary {
+    pub realtime_feed_id: String,
+    pub entity_id: String,
+    pub trip_id: Option<String>,
+    pub route_id: Option<String>,
+    pub start_date: Option<String>,
+    pub start_time: Option<String>,
+    pub schedule_relationship: Option<i32>,
+    pub vehicle_id: Option<String>,
+    pub delay: Option<i32>,
+    pub timestamp: Option<u64>,
+    pub stop_time_updates: Vec<StopTimeUpdateSummary>,
+}
+
+/// One entity an alert applies to. Every field set has to match.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InformedEntitySummary {
+    pub agency_id: Option<String>,
+    pub route_id: Option<String>,
+    pub route_type: Option<i32>,
+    pub trip_id: Option<String>,
+    pub stop_id: Option<String>,
+}
+
+/// A service alert from a realtime feed. Texts use the first translation given.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AlertSummary {
+    pub realtime_feed_id: String,
+    pub entity_id: String,
+    pub cause: Option<i32>,
+    pub effect: Option<i32>,
+    pub header_text: Option<String>,
+    pub description_text: Option<String>,
+    pub url: Option<String>,
+    pub active_periods: Vec<(Option<u64>, Option<u64>)>,
+    pub informed_entities: Vec<InformedEntitySummary>,
+}
+
+/// How old the data aspen holds for a realtime feed is.
+/// received_at values are unix milliseconds, feed timestamps are the unix seconds in the feed header.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeedFreshness {
+    pub realtime_feed_id: String,
+    pub vehicles_received_at: Option<u64>,
+    pub vehicles_feed_timestamp: Option<u64>,
+    pub trips_received_at: Option<u64>,
+    pub trips_feed_timestamp: Option<u64>,
+    pub alerts_received_at: Option<u64>,
+    pub alerts_feed_timestamp: Option<u64>,
+}
+
 /// This is the service definition. It looks a lot like a trait definition.
 /// It defines one RPC, hello, which takes one arg, name, and returns a String.
 #[tarpc::service]
@@ -8,11 +105,35 @@ pub trait AspenRpc {
     /// Returns a greeting for name.
     async fn hello(name: String) -> String;
 
-    //maybesend gtfs rt?
+    /// Stores the latest vehicles, trip updates and alerts protobufs for a realtime feed.
     async fn new_rt_kactus(
         realtime_feed_id: String,
         vehicles: Option<Vec<u8>>,
         trips: Option<Vec<u8>>,
         alerts: Option<Vec<u8>>,
-    ) -> bool;
+    ) -> Result<(), RtDecodeErrors>;
+
+    /// Returns every vehicle position within the bounding box, across all feeds.
+    async fn get_vehicles_in_bbox(
+        min_lat: f32,
+        min_lon: f32,
+        max_lat: f32,
+        max_lon: f32,
+    ) -> Vec<VehiclePositionSummary>;
+
+    /// Returns the trip updates for a trip. There can be more than one when a trip runs on several start dates.
+    async fn get_trip_updates(realtime_feed_id: String, trip_id: String) -> Vec<TripUpdateSummary>;
+
+    /// Returns the alerts of a feed with an informed entity matching the route, route type, trip and stop given,
+    /// including agency wide alerts. Every field an informed entity sets has to be given and equal.
+    async fn get_alerts(
+        realtime_feed_id: String,
+        route_id: Option<String>,
+        route_type: Option<i32>,
+        trip_id: Option<String>,
+        stop_id: Option<String>,
+    ) -> Vec<AlertSummary>;
+
+    /// Returns when each realtime feed was last received.
+    async fn get_feed_freshness() -> Vec<FeedFreshness>;
 }
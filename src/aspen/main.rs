@@ -1,4 +1,6 @@
 use aspenlib::AspenRpc;
+use aspenlib::RtDecodeErrors;
+use aspenlib::{AlertSummary, FeedFreshness, TripUpdateSummary, VehiclePositionSummary};
 use clap::Parser;
 use futures::{future, prelude::*};
 use rand::{
@@ -17,18 +19,34 @@ use tarpc::{
 use tokio::time;
 
 mod import_kactus;
+mod query_realtime;
+#[path = "rail-location-interpolation.rs"]
+mod rail_location_interpolation;
+mod realtime_store;
+
+use rail_location_interpolation::{PostgresPool, StaticRailCache};
+use realtime_store::RealtimeStore;
+use tokio_postgres::NoTls;
 
 #[derive(Parser)]
 struct Flags {
     /// Sets the port number to listen on.
     #[clap(long)]
     port: u16,
+    /// Postgres connection string, used to read static schedules for synthesized vehicle positions.
+    #[clap(long)]
+    postgres: Option<String>,
 }
 
 // This is the type that implements the generated World trait. It is the business logic
 // and is used to start the server.
 #[derive(Clone)]
-struct AspenServer(SocketAddr);
+struct AspenServer {
+    addr: SocketAddr,
+    realtime_store: RealtimeStore,
+    pool: Option<PostgresPool>,
+    static_rail_cache: StaticRailCache,
+}
 
 #[tarpc::server]
 impl AspenRpc for AspenServer {
@@ -36,7 +54,7 @@ impl AspenRpc for AspenServer {
         let sleep_time =
             Duration::from_millis(Uniform::new_inclusive(1, 10).sample(&mut thread_rng()));
         time::sleep(sleep_time).await;
-        format!("Hello, {name}! You are connected from {}", self.0)
+        format!("Hello, {name}! You are connected from {}", self.addr)
     }
 
     async fn new_rt_kactus(
@@ -46,8 +64,62 @@ impl AspenRpc for AspenServer {
         vehicles: Option<Vec<u8>>,
         trips: Option<Vec<u8>>,
         alerts: Option<Vec<u8>>,
-    ) -> bool {
-        import_kactus::new_rt_kactus(realtime_feed_id, vehicles, trips, alerts).await
+    ) -> Result<(), RtDecodeErrors> {
+        import_kactus::new_rt_kactus(
+            &self.realtime_store,
+            &self.pool,
+            &self.static_rail_cache,
+            realtime_feed_id,
+            vehicles,
+            trips,
+            alerts,
+        )
+        .await
+    }
+
+    async fn get_vehicles_in_bbox(
+        self,
+        _: context::Context,
+        min_lat: f32,
+        min_lon: f32,
+        max_lat: f32,
+        max_lon: f32,
+    ) -> Vec<VehiclePositionSummary> {
+        query_realtime::vehicles_in_bbox(&self.realtime_store, min_lat, min_lon, max_lat, max_lon)
+            .await
+    }
+
+    async fn get_trip_updates(
+        self,
+        _: context::Context,
+        realtime_feed_id: String,
+        trip_id: String,
+    ) -> Vec<TripUpdateSummary> {
+        query_realtime::trip_updates(&self.realtime_store, &realtime_feed_id, &trip_id).await
+    }
+
+    async fn get_alerts(
+        self,
+        _: context::Context,
+        realtime_feed_id: String,
+        route_id: Option<String>,
+        route_type: Option<i32>,
+        trip_id: Option<String>,
+        stop_id: Option<String>,
+    ) -> Vec<AlertSummary> {
+        query_realtime::alerts(
+            &self.realtime_store,
+            &realtime_feed_id,
+            route_id,
+            route_type,
+            trip_id,
+            stop_id,
+        )
+        .await
+    }
+
+    async fn get_feed_freshness(self, _: context::Context) -> Vec<FeedFreshness> {
+        query_realtime::feed_freshness(&self.realtime_store).await
     }
 }
 
@@ -58,6 +130,22 @@ async fn main() -> anyhow::Result<()> {
 
     let server_addr = (IpAddr::V6(Ipv6Addr::LOCALHOST), flags.port);
 
+    //latest realtime data for every feed, shared by every connection
+    let realtime_store = realtime_store::new_realtime_store();
+    let static_rail_cache = rail_location_interpolation::new_static_rail_cache();
+
+    let pool = match &flags.postgres {
+        Some(postgresstring) => {
+            let manager =
+                bb8_postgres::PostgresConnectionManager::new(postgresstring.parse()?, NoTls);
+            Some(bb8::Pool::builder().build(manager).await?)
+        }
+        None => {
+            println!("No postgres string given, vehicle positions will not be synthesized");
+            None
+        }
+    };
+
     // JSON transport is provided by the json_transport tarpc module. It makes it easy
     // to start up a serde-powered json serialization strategy over TCP.
     let mut listener = tarpc::serde_transport::tcp::listen(&server_addr, Json::default).await?;
@@ -71,7 +159,12 @@ async fn main() -> anyhow::Result<()> {
         // serve is generated by the service attribute. It takes as input any type implementing
         // the generated World trait.
         .map(|channel| {
-            let server = AspenServer(channel.transport().peer_addr().unwrap());
+            let server = AspenServer {
+                addr: channel.transport().peer_addr().unwrap(),
+                realtime_store: realtime_store.clone(),
+                pool: pool.clone(),
+                static_rail_cache: static_rail_cache.clone(),
+            };
             channel.execute(server.serve())
         })
         // Max 10 channels.
@@ -0,0 +1,261 @@
+use crate::realtime_store::{FeedData, RealtimeStore};
+use aspenlib::{
+    AlertSummary, FeedFreshness, InformedEntitySummary, StopTimeUpdateSummary, TripUpdateSummary,
+    VehiclePositionSummary,
+};
+
+fn first_translation(input: &Option<gtfs_rt::TranslatedString>) -> Option<String> {
+    input
+        .as_ref()
+        .and_then(|translated| translated.translation.first())
+        .map(|translation| translation.text.to_owned())
+}
+
+pub async fn vehicles_in_bbox(
+    store: &RealtimeStore,
+    min_lat: f32,
+    min_lon: f32,
+    max_lat: f32,
+    max_lon: f32,
+) -> Vec<VehiclePositionSummary> {
+    let store = store.read().await;
+
+    let mut result = vec![];
+
+    for (realtime_feed_id, feed_store) in store.iter() {
+        if let Some(vehicles) = &feed_store.vehicles {
+            for entity in &vehicles.message.entity {
+                if let Some(vehicle) = &entity.vehicle {
+                    if let Some(position) = &vehicle.position {
+                        if position.latitude >= min_lat
+                            && position.latitude <= max_lat
+                            && position.longitude >= min_lon
+                            && position.longitude <= max_lon
+                        {
+                            result.push(VehiclePositionSummary {
+                                realtime_feed_id: realtime_feed_id.to_owned(),
+                                entity_id: entity.id.to_owned(),
+                                trip_id: vehicle
+                                    .trip
+                                    .as_ref()
+                                    .and_then(|trip| trip.trip_id.to_owned()),
+                                route_id: vehicle
+                                    .trip
+                                    .as_ref()
+                                    .and_then(|trip| trip.route_id.to_owned()),
+                                vehicle_id: vehicle
+                                    .vehicle
+                                    .as_ref()
+                                    .and_then(|descriptor| descriptor.id.to_owned()),
+                                vehicle_label: vehicle
+                                    .vehicle
+                                    .as_ref()
+                                    .and_then(|descriptor| descriptor.label.to_owned()),
+                                latitude: position.latitude,
+                                longitude: position.longitude,
+                                bearing: position.bearing,
+                                speed: position.speed,
+                                current_stop_sequence: vehicle.current_stop_sequence,
+                                stop_id: vehicle.stop_id.to_owned(),
+                                timestamp: vehicle.timestamp,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    result
+}
+
+pub async fn trip_updates(
+    store: &RealtimeStore,
+    realtime_feed_id: &str,
+    trip_id: &str,
+) -> Vec<TripUpdateSummary> {
+    let store = store.read().await;
+
+    let trips = match store
+        .get(realtime_feed_id)
+        .and_then(|feed_store| feed_store.trips.as_ref())
+    {
+        Some(trips) => trips,
+        None => return vec![],
+    };
+
+    trips
+        .message
+        .entity
+        .iter()
+        .filter_map(|entity| {
+            entity
+                .trip_update
+                .as_ref()
+                .map(|trip_update| (entity, trip_update))
+        })
+        .filter(|(_, trip_update)| trip_update.trip.trip_id.as_deref() == Some(trip_id))
+        .map(|(entity, trip_update)| TripUpdateSummary {
+            realtime_feed_id: realtime_feed_id.to_string(),
+            entity_id: entity.id.to_owned(),
+            trip_id: trip_update.trip.trip_id.to_owned(),
+            route_id: trip_update.trip.route_id.to_owned(),
+            start_date: trip_update.trip.start_date.to_owned(),
+            start_time: trip_update.trip.start_time.to_owned(),
+            schedule_relationship: trip_update.trip.schedule_relationship,
+            vehicle_id: trip_update
+                .vehicle
+                .as_ref()
+                .and_then(|vehicle| vehicle.id.to_owned()),
+            delay: trip_update.delay,
+            timestamp: trip_update.timestamp,
+            stop_time_updates: trip_update
+                .stop_time_update
+                .iter()
+                .map(|stop_time_update| StopTimeUpdateSummary {
+                    stop_sequence: stop_time_update.stop_sequence,
+                    stop_id: stop_time_update.stop_id.to_owned(),
+                    arrival_time: stop_time_update
+                        .arrival
+                        .as_ref()
+                        .and_then(|event| event.time),
+                    arrival_delay: stop_time_update
+                        .arrival
+                        .as_ref()
+                        .and_then(|event| event.delay),
+                    departure_time: stop_time_update
+                        .departure
+                        .as_ref()
+                        .and_then(|event| event.time),
+                    departure_delay: stop_time_update
+                        .departure
+                        .as_ref()
+                        .and_then(|event| event.delay),
+                    schedule_relationship: stop_time_update.schedule_relationship,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+//every field the informed entity sets has to match, a field left out of the query can't match
+//an informed entity with only an agency set applies to the whole agency, otherwise the agency isn't compared,
+//routes, trips and stops already belong to one
+fn informed_entity_matches(
+    informed_entity: &gtfs_rt::EntitySelector,
+    route_id: &Option<String>,
+    route_type: &Option<i32>,
+    trip_id: &Option<String>,
+    stop_id: &Option<String>,
+) -> bool {
+    let selected_trip_id = informed_entity
+        .trip
+        .as_ref()
+        .and_then(|trip| trip.trip_id.as_ref());
+    let selected_trip_route_id = informed_entity
+        .trip
+        .as_ref()
+        .and_then(|trip| trip.route_id.as_ref());
+
+    let selects_something = informed_entity.route_id.is_some()
+        || informed_entity.route_type.is_some()
+        || selected_trip_id.is_some()
+        || selected_trip_route_id.is_some()
+        || informed_entity.stop_id.is_some();
+
+    if !selects_something {
+        return informed_entity.agency_id.is_some();
+    }
+
+    let field_matches = |selected: Option<&String>, queried: &Option<String>| {
+        selected.map_or(true, |selected| queried.as_ref() == Some(selected))
+    };
+
+    field_matches(informed_entity.route_id.as_ref(), route_id)
+        && field_matches(selected_trip_route_id, route_id)
+        && field_matches(selected_trip_id, trip_id)
+        && field_matches(informed_entity.stop_id.as_ref(), stop_id)
+        && informed_entity
+            .route_type
+            .map_or(true, |selected| *route_type == Some(selected))
+}
+
+pub async fn alerts(
+    store: &RealtimeStore,
+    realtime_feed_id: &str,
+    route_id: Option<String>,
+    route_type: Option<i32>,
+    trip_id: Option<String>,
+    stop_id: Option<String>,
+) -> Vec<AlertSummary> {
+    let store = store.read().await;
+
+    let alerts = match store
+        .get(realtime_feed_id)
+        .and_then(|feed_store| feed_store.alerts.as_ref())
+    {
+        Some(alerts) => alerts,
+        None => return vec![],
+    };
+
+    alerts
+        .message
+        .entity
+        .iter()
+        .filter_map(|entity| entity.alert.as_ref().map(|alert| (entity, alert)))
+        .filter(|(_, alert)| {
+            alert.informed_entity.iter().any(|informed_entity| {
+                informed_entity_matches(informed_entity, &route_id, &route_type, &trip_id, &stop_id)
+            })
+        })
+        .map(|(entity, alert)| AlertSummary {
+            realtime_feed_id: realtime_feed_id.to_string(),
+            entity_id: entity.id.to_owned(),
+            cause: alert.cause,
+            effect: alert.effect,
+            header_text: first_translation(&alert.header_text),
+            description_text: first_translation(&alert.description_text),
+            url: first_translation(&alert.url),
+            active_periods: alert
+                .active_period
+                .iter()
+                .map(|period| (period.start, period.end))
+                .collect(),
+            informed_entities: alert
+                .informed_entity
+                .iter()
+                .map(|informed_entity| InformedEntitySummary {
+                    agency_id: informed_entity.agency_id.to_owned(),
+                    route_id: informed_entity.route_id.to_owned(),
+                    route_type: informed_entity.route_type,
+                    trip_id: informed_entity
+                        .trip
+                        .as_ref()
+                        .and_then(|trip| trip.trip_id.to_owned()),
+                    stop_id: informed_entity.stop_id.to_owned(),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+pub async fn feed_freshness(store: &RealtimeStore) -> Vec<FeedFreshness> {
+    let store = store.read().await;
+
+    let received_at = |data: &Option<FeedData>| data.as_ref().map(|data| data.received_at_ms);
+    let feed_timestamp =
+        |data: &Option<FeedData>| data.as_ref().and_then(|data| data.feed_timestamp());
+
+    store
+        .iter()
+        .map(|(realtime_feed_id, feed_store)| FeedFreshness {
+            realtime_feed_id: realtime_feed_id.to_owned(),
+            vehicles_received_at: received_at(&feed_store.vehicles),
+            vehicles_feed_timestamp: feed_timestamp(&feed_store.vehicles),
+            trips_received_at: received_at(&feed_store.trips),
+            trips_feed_timestamp: feed_timestamp(&feed_store.trips),
+            alerts_received_at: received_at(&feed_store.alerts),
+            alerts_feed_timestamp: feed_timestamp(&feed_store.alerts),
+        })
+        .collect()
+}
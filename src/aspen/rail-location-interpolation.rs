@@ -1,10 +1,573 @@
 //try to get it to work on MTA and BART using the same algorithms
 
 //adapt for Vancouver Skytrain later, i don't want to worry about that right now.
-async fn make_new_vehicle_feed(gtfs_rt_id: &str, gtfs_static_id: &str, gtfs_trips_data: gtfs_rt::FeedMessage) -> gtfs_rt::FeedMessage {
-    //what do i return? Maybe GTFS RT?
+use aspenlib::gtfs_time;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use geo::{LineInterpolatePoint, LineLocatePoint};
+use geo_postgis::FromPostgis;
+use postgis::ewkb;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tokio_postgres::NoTls;
 
-    //or do I make it a sideeffect and insert into DB? Not sure yet.
+pub type PostgresPool = bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>;
 
-    //also the feed needs to pull in GTFS static data like the stops coordinates right? But that's an excessive Postgres request.
-}
\ No newline at end of file
+//static data is reloaded once a day, it only changes when the importer runs
+const STATIC_DATA_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24);
+//a feed that failed to load isn't retried on every poll
+const FAILED_LOAD_RETRY: Duration = Duration::from_secs(60 * 5);
+
+pub struct StaticStopTime {
+    pub stop_id: String,
+    pub arrival_time: Option<i64>,
+    pub departure_time: Option<i64>,
+}
+
+pub struct StaticRailData {
+    pub loaded_at: Instant,
+    //agency timezone of the static feed, schedule times are seconds into a service day there
+    pub timezone: Tz,
+    pub stops: HashMap<String, geo::Point>,
+    pub trip_shapes: HashMap<String, String>,
+    pub shapes: HashMap<String, geo::LineString>,
+    pub trip_stop_times: HashMap<String, Vec<StaticStopTime>>,
+    //MTA realtime trip ids are the end of the static trip id, "000600_1..S03R" for "AFA23GEN-1091-Weekday-00_000600_1..S03R"
+    //the same suffix is used by the Weekday, Saturday and Sunday trips, so each one keeps every (trip_id, service_id)
+    pub trip_id_suffixes: HashMap<String, Vec<(String, String)>>,
+    //service_id -> every date it runs on
+    pub service_days: HashMap<String, HashSet<NaiveDate>>,
+}
+
+impl StaticRailData {
+    //a suffix shared by several trips picks the one whose service runs on the service date
+    //None when that still leaves more than one, a wrong trip is worse than no vehicle
+    //trip_id_prefix is for regional feeds that put the agency id in front of the trip id, "BA:" in the 511 feed
+    fn static_trip_id<'a>(
+        &'a self,
+        realtime_trip_id: &'a str,
+        trip_id_prefix: Option<&str>,
+        service_date: Option<NaiveDate>,
+    ) -> Option<&'a str> {
+        if self.trip_stop_times.contains_key(realtime_trip_id) {
+            return Some(realtime_trip_id);
+        }
+
+        if let Some(prefix) = trip_id_prefix {
+            if let Some((trip_id, _)) = self
+                .trip_stop_times
+                .get_key_value(&format!("{}{}", prefix, realtime_trip_id))
+            {
+                return Some(trip_id.as_str());
+            }
+        }
+
+        let candidates = self.trip_id_suffixes.get(realtime_trip_id)?;
+
+        if candidates.len() == 1 {
+            return Some(candidates[0].0.as_str());
+        }
+
+        let service_date = service_date?;
+
+        let mut running = candidates.iter().filter(|(_, service_id)| {
+            self.service_days
+                .get(service_id)
+                .map_or(false, |days| days.contains(&service_date))
+        });
+
+        match (running.next(), running.next()) {
+            (Some((trip_id, _)), None) => Some(trip_id.as_str()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct StaticRailCacheEntry {
+    //kept while a reload runs or after a reload fails, stale data beats no vehicles
+    data: Option<Arc<StaticRailData>>,
+    //only one load per feed runs at a time
+    loading: bool,
+    failed_at: Option<Instant>,
+}
+
+pub type StaticRailCache = Arc<RwLock<HashMap<String, StaticRailCacheEntry>>>;
+
+pub fn new_static_rail_cache() -> StaticRailCache {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+async fn load_static_rail_data(
+    pool: &PostgresPool,
+    gtfs_static_id: &str,
+) -> Result<StaticRailData, Box<dyn std::error::Error + Send + Sync>> {
+    let client = pool.get().await?;
+
+    let timezone = client
+        .query_opt(
+            "SELECT default_timezone FROM gtfs.static_feeds WHERE onestop_feed_id = $1;",
+            &[&gtfs_static_id],
+        )
+        .await?
+        .and_then(|row| gtfs_time::parse_timezone(row.get::<_, Option<String>>(0).as_deref()))
+        .unwrap_or_else(|| {
+            println!("No timezone for {}, using UTC", gtfs_static_id);
+            Tz::UTC
+        });
+
+    let stops = client
+        .query(
+            "SELECT gtfs_id, ST_X(point), ST_Y(point) FROM gtfs.stops WHERE onestop_feed_id = $1;",
+            &[&gtfs_static_id],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            (
+                row.get::<_, String>(0),
+                geo::Point::new(row.get::<_, f64>(1), row.get::<_, f64>(2)),
+            )
+        })
+        .collect::<HashMap<String, geo::Point>>();
+
+    let mut trip_shapes: HashMap<String, String> = HashMap::new();
+    let mut trip_services: HashMap<String, String> = HashMap::new();
+
+    for row in client
+        .query(
+            "SELECT trip_id, shape_id, service_id FROM gtfs.trips WHERE onestop_feed_id = $1;",
+            &[&gtfs_static_id],
+        )
+        .await?
+    {
+        let trip_id: String = row.get(0);
+
+        if let Some(shape_id) = row.get::<_, Option<String>>(1) {
+            trip_shapes.insert(trip_id.to_owned(), shape_id);
+        }
+
+        trip_services.insert(trip_id, row.get(2));
+    }
+
+    let service_days = client
+        .query(
+            "SELECT service_id, active_dates FROM gtfs.service_days WHERE onestop_feed_id = $1;",
+            &[&gtfs_static_id],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            (
+                row.get::<_, String>(0),
+                row.get::<_, Vec<NaiveDate>>(1)
+                    .into_iter()
+                    .collect::<HashSet<NaiveDate>>(),
+            )
+        })
+        .collect::<HashMap<String, HashSet<NaiveDate>>>();
+
+    let shapes = client
+        .query(
+            "SELECT shape_id, linestring FROM gtfs.shapes WHERE onestop_feed_id = $1;",
+            &[&gtfs_static_id],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            let linestring: ewkb::LineStringT<ewkb::Point> = row.get(1);
+            (
+                row.get::<_, String>(0),
+                geo_types::LineString::from_postgis(&linestring),
+            )
+        })
+        .collect::<HashMap<String, geo::LineString>>();
+
+    let mut trip_stop_times: HashMap<String, Vec<StaticStopTime>> = HashMap::new();
+
+    for row in client
+        .query(
+            "SELECT trip_id, stop_id, arrival_time, departure_time FROM gtfs.stoptimes
+            WHERE onestop_feed_id = $1 ORDER BY trip_id, stop_sequence;",
+            &[&gtfs_static_id],
+        )
+        .await?
+    {
+        trip_stop_times
+            .entry(row.get(0))
+            .or_insert(vec![])
+            .push(StaticStopTime {
+                stop_id: row.get(1),
+                arrival_time: row.get(2),
+                departure_time: row.get(3),
+            });
+    }
+
+    let mut trip_id_suffixes: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+    for trip_id in trip_stop_times.keys() {
+        if let (Some((_, suffix)), Some(service_id)) =
+            (trip_id.split_once('_'), trip_services.get(trip_id))
+        {
+            trip_id_suffixes
+                .entry(suffix.to_string())
+                .or_insert(vec![])
+                .push((trip_id.to_owned(), service_id.to_owned()));
+        }
+    }
+
+    println!(
+        "Loaded {} stops, {} shapes and {} trips for {}",
+        stops.len(),
+        shapes.len(),
+        trip_stop_times.len(),
+        gtfs_static_id
+    );
+
+    Ok(StaticRailData {
+        loaded_at: Instant::now(),
+        timezone: timezone,
+        stops: stops,
+        trip_shapes: trip_shapes,
+        shapes: shapes,
+        trip_stop_times: trip_stop_times,
+        trip_id_suffixes: trip_id_suffixes,
+        service_days: service_days,
+    })
+}
+
+//loading a big feed takes a while, so it happens in the background and never inside the rpc call
+//returns None until the first load for the feed has finished
+pub async fn get_static_rail_data(
+    pool: &PostgresPool,
+    cache: &StaticRailCache,
+    gtfs_static_id: &str,
+) -> Option<Arc<StaticRailData>> {
+    if let Some(entry) = cache.read().await.get(gtfs_static_id) {
+        if let Some(static_data) = &entry.data {
+            if static_data.loaded_at.elapsed() < STATIC_DATA_MAX_AGE {
+                return Some(static_data.clone());
+            }
+        }
+    }
+
+    let mut cache_write = cache.write().await;
+    let entry = cache_write.entry(gtfs_static_id.to_string()).or_default();
+
+    let recently_failed = entry
+        .failed_at
+        .map_or(false, |failed_at| failed_at.elapsed() < FAILED_LOAD_RETRY);
+
+    let needs_load = match &entry.data {
+        Some(static_data) => static_data.loaded_at.elapsed() >= STATIC_DATA_MAX_AGE,
+        None => true,
+    };
+
+    if needs_load && !entry.loading && !recently_failed {
+        entry.loading = true;
+
+        tokio::spawn(load_in_background(
+            pool.clone(),
+            cache.clone(),
+            gtfs_static_id.to_string(),
+        ));
+    }
+
+    entry.data.clone()
+}
+
+async fn load_in_background(pool: PostgresPool, cache: StaticRailCache, gtfs_static_id: String) {
+    let result = load_static_rail_data(&pool, &gtfs_static_id).await;
+
+    let mut cache_write = cache.write().await;
+    let entry = cache_write.entry(gtfs_static_id.to_owned()).or_default();
+    entry.loading = false;
+
+    match result {
+        Ok(static_data) => {
+            entry.data = Some(Arc::new(static_data));
+            entry.failed_at = None;
+        }
+        Err(err) => {
+            println!(
+                "Could not load static data for {}: {:?}",
+                gtfs_static_id, err
+            );
+            entry.failed_at = Some(Instant::now());
+        }
+    }
+}
+
+//initial bearing from a to b in degrees clockwise from north
+fn bearing_degrees(a: &geo::Point, b: &geo::Point) -> f32 {
+    let (lat_a, lat_b) = (a.y().to_radians(), b.y().to_radians());
+    let delta_lon = (b.x() - a.x()).to_radians();
+
+    let y = delta_lon.sin() * lat_b.cos();
+    let x = lat_a.cos() * lat_b.sin() - lat_a.sin() * lat_b.cos() * delta_lon.cos();
+
+    ((y.atan2(x).to_degrees() + 360.) % 360.) as f32
+}
+
+//position and bearing a fraction of the way from the previous stop to the next stop
+//follows the shape when both stops can be placed on it in order, otherwise goes in a straight line
+fn interpolate_between_stops(
+    shape: Option<&geo::LineString>,
+    previous_stop: &geo::Point,
+    next_stop: &geo::Point,
+    fraction: f64,
+) -> (geo::Point, f32) {
+    if let Some(shape) = shape {
+        let located = (
+            shape.line_locate_point(previous_stop),
+            shape.line_locate_point(next_stop),
+        );
+
+        if let (Some(start), Some(end)) = located {
+            if end > start {
+                let position = start + (end - start) * fraction;
+                let ahead = f64::min(position + 0.0005, end);
+
+                if let (Some(point), Some(point_ahead)) = (
+                    shape.line_interpolate_point(position),
+                    shape.line_interpolate_point(ahead),
+                ) {
+                    let bearing = match point == point_ahead {
+                        true => bearing_degrees(previous_stop, next_stop),
+                        false => bearing_degrees(&point, &point_ahead),
+                    };
+                    return (point, bearing);
+                }
+            }
+        }
+    }
+
+    let point = geo::Point::new(
+        previous_stop.x() + (next_stop.x() - previous_stop.x()) * fraction,
+        previous_stop.y() + (next_stop.y() - previous_stop.y()) * fraction,
+    );
+
+    (point, bearing_degrees(previous_stop, next_stop))
+}
+
+//without a start_date the trip is assumed to run on today's service day in the feed's timezone
+fn service_date(
+    static_data: &StaticRailData,
+    trip_update: &gtfs_rt::TripUpdate,
+    now: i64,
+) -> Option<NaiveDate> {
+    trip_update
+        .trip
+        .start_date
+        .as_deref()
+        .and_then(gtfs_time::parse_gtfs_date)
+        .or_else(|| {
+            chrono::DateTime::from_timestamp(now, 0)
+                .map(|now| now.with_timezone(&static_data.timezone).date_naive())
+        })
+}
+
+//(stop_id, predicted unix time) for every stop time update with a time, or with a delay that can be added to the schedule
+fn predicted_stops(
+    static_data: &StaticRailData,
+    trip_id_prefix: Option<&str>,
+    trip_update: &gtfs_rt::TripUpdate,
+    now: i64,
+) -> Vec<(String, i64)> {
+    let service_date = service_date(static_data, trip_update, now);
+
+    let scheduled_stop_times = trip_update
+        .trip
+        .trip_id
+        .as_ref()
+        .and_then(|trip_id| static_data.static_trip_id(trip_id, trip_id_prefix, service_date))
+        .and_then(|trip_id| static_data.trip_stop_times.get(trip_id));
+
+    trip_update
+        .stop_time_update
+        .iter()
+        .filter_map(|stop_time_update| {
+            let stop_id = stop_time_update.stop_id.as_ref()?;
+
+            let scheduled_time = |arrival: bool| -> Option<i64> {
+                let stop_time = scheduled_stop_times?
+                    .iter()
+                    .find(|stop_time| &stop_time.stop_id == stop_id)?;
+                let seconds = match arrival {
+                    true => stop_time.arrival_time.or(stop_time.departure_time),
+                    false => stop_time.departure_time.or(stop_time.arrival_time),
+                }?;
+
+                gtfs_time::gtfs_time_to_utc(&static_data.timezone, service_date?, seconds)
+                    .map(|time| time.timestamp())
+            };
+
+            let event_time = |event: &gtfs_rt::trip_update::StopTimeEvent, arrival: bool| {
+                event.time.or_else(|| {
+                    event
+                        .delay
+                        .and_then(|delay| scheduled_time(arrival).map(|time| time + delay as i64))
+                })
+            };
+
+            let time = stop_time_update
+                .arrival
+                .as_ref()
+                .and_then(|event| event_time(event, true))
+                .or(stop_time_update
+                    .departure
+                    .as_ref()
+                    .and_then(|event| event_time(event, false)));
+
+            time.map(|time| (stop_id.to_owned(), time))
+        })
+        .collect()
+}
+
+fn make_vehicle_position(
+    static_data: &StaticRailData,
+    trip_id_prefix: Option<&str>,
+    trip_update: &gtfs_rt::TripUpdate,
+    now: i64,
+) -> Option<gtfs_rt::VehiclePosition> {
+    let predicted_stops = predicted_stops(static_data, trip_id_prefix, trip_update, now);
+
+    //feeds drop stops the train has already left, the first stop in the future is where it's heading
+    let next_index = predicted_stops.iter().position(|(_, time)| *time >= now)?;
+    let (next_stop_id, next_time) = &predicted_stops[next_index];
+    let next_stop = static_data.stops.get(next_stop_id)?;
+
+    let service_date = service_date(static_data, trip_update, now);
+
+    let static_trip_id = trip_update
+        .trip
+        .trip_id
+        .as_ref()
+        .and_then(|trip_id| static_data.static_trip_id(trip_id, trip_id_prefix, service_date));
+
+    let shape = static_trip_id
+        .and_then(|trip_id| static_data.trip_shapes.get(trip_id))
+        .and_then(|shape_id| static_data.shapes.get(shape_id));
+
+    let previous: Option<(String, i64)> = match next_index {
+        0 => {
+            //use the schedule to find the stop before and how long the train needs between them
+            static_trip_id
+                .and_then(|trip_id| static_data.trip_stop_times.get(trip_id))
+                .and_then(|stop_times| {
+                    let index = stop_times
+                        .iter()
+                        .position(|stop_time| &stop_time.stop_id == next_stop_id)?;
+                    if index == 0 {
+                        return None;
+                    }
+
+                    let previous_stop_time = &stop_times[index - 1];
+                    let scheduled_previous = previous_stop_time
+                        .departure_time
+                        .or(previous_stop_time.arrival_time)?;
+                    let scheduled_next = stop_times[index]
+                        .arrival_time
+                        .or(stop_times[index].departure_time)?;
+
+                    Some((
+                        previous_stop_time.stop_id.to_owned(),
+                        next_time - (scheduled_next - scheduled_previous),
+                    ))
+                })
+        }
+        _ => Some(predicted_stops[next_index - 1].to_owned()),
+    };
+
+    let (point, bearing, status) = match previous
+        .as_ref()
+        .and_then(|(stop_id, time)| static_data.stops.get(stop_id).map(|point| (point, time)))
+    {
+        Some((previous_stop, previous_time)) => {
+            let fraction = match next_time - previous_time {
+                duration if duration > 0 => {
+                    ((now - previous_time) as f64 / duration as f64).clamp(0., 1.)
+                }
+                _ => 1.,
+            };
+
+            let (point, bearing) =
+                interpolate_between_stops(shape, previous_stop, next_stop, fraction);
+
+            (
+                point,
+                Some(bearing),
+                gtfs_rt::vehicle_position::VehicleStopStatus::InTransitTo,
+            )
+        }
+        //no stop before this one, the train is waiting at its origin
+        None => (
+            *next_stop,
+            None,
+            gtfs_rt::vehicle_position::VehicleStopStatus::StoppedAt,
+        ),
+    };
+
+    Some(gtfs_rt::VehiclePosition {
+        trip: Some(trip_update.trip.clone()),
+        vehicle: trip_update.vehicle.clone(),
+        position: Some(gtfs_rt::Position {
+            latitude: point.y() as f32,
+            longitude: point.x() as f32,
+            bearing: bearing,
+            ..Default::default()
+        }),
+        stop_id: Some(next_stop_id.to_owned()),
+        current_status: Some(status as i32),
+        timestamp: Some(now as u64),
+        ..Default::default()
+    })
+}
+
+//estimates where each train is along its shape from the trip updates, returns a vehicle positions feed
+pub fn make_new_vehicle_feed(
+    gtfs_rt_id: &str,
+    static_data: &StaticRailData,
+    trip_id_prefix: Option<&str>,
+    gtfs_trips_data: &gtfs_rt::FeedMessage,
+) -> gtfs_rt::FeedMessage {
+    let now = gtfs_trips_data
+        .header
+        .timestamp
+        .map(|timestamp| timestamp as i64)
+        .unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let entities = gtfs_trips_data
+        .entity
+        .iter()
+        .filter_map(|entity| {
+            let trip_update = entity.trip_update.as_ref()?;
+            let vehicle = make_vehicle_position(static_data, trip_id_prefix, trip_update, now)?;
+
+            Some(gtfs_rt::FeedEntity {
+                id: entity.id.to_owned(),
+                vehicle: Some(vehicle),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<gtfs_rt::FeedEntity>>();
+
+    println!(
+        "Made {} vehicles out of {} trip updates for {}",
+        entities.len(),
+        gtfs_trips_data.entity.len(),
+        gtfs_rt_id
+    );
+
+    gtfs_rt::FeedMessage {
+        header: gtfs_rt::FeedHeader {
+            gtfs_realtime_version: gtfs_trips_data.header.gtfs_realtime_version.to_owned(),
+            timestamp: Some(now as u64),
+            ..Default::default()
+        },
+        entity: entities,
+        ..Default::default()
+    }
+}
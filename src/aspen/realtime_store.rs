@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+pub struct FeedData {
+    pub message: gtfs_rt::FeedMessage,
+    //when aspen received it, in unix ms
+    pub received_at_ms: u64,
+}
+
+impl FeedData {
+    pub fn new(message: gtfs_rt::FeedMessage) -> FeedData {
+        FeedData {
+            message: message,
+            received_at_ms: unix_ms_now(),
+        }
+    }
+
+    //the timestamp the agency put in the feed header, in unix seconds
+    pub fn feed_timestamp(&self) -> Option<u64> {
+        self.message.header.timestamp
+    }
+}
+
+//latest realtime data for a single realtime_feed_id
+#[derive(Default)]
+pub struct RealtimeFeedStore {
+    pub vehicles: Option<FeedData>,
+    pub trips: Option<FeedData>,
+    pub alerts: Option<FeedData>,
+}
+
+pub type RealtimeStore = Arc<RwLock<HashMap<String, RealtimeFeedStore>>>;
+
+pub fn new_realtime_store() -> RealtimeStore {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+pub fn unix_ms_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
@@ -2,7 +2,10 @@
 use actix_web::dev::Service;
 use actix_web::middleware::DefaultHeaders;
 use actix_web::{middleware, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use aspenlib::gtfs_time;
 use bb8::Pool;
+use chrono::{NaiveDate, NaiveTime, Timelike};
+use chrono_tz::Tz;
 use qstring::QString;
 use r2d2_postgres::{postgres::NoTls, PostgresConnectionManager};
 use serde_json::to_string;
@@ -72,6 +75,8 @@ struct RouteOutPostgres {
     continuous_pickup: i16,
     continuous_drop_off: i16,
     shapes_list: Vec<String>,
+    //a lighter version of color for the dark basemap
+    dark_color: Option<String>,
 }
 
 #[derive(serde::Serialize)]
@@ -307,7 +312,8 @@ pub async fn getroutesperagency(
      text_color,
      continuous_pickup,
      continuous_drop_off,
-     shapes_list FROM gtfs.routes WHERE onestop_feed_id = $1;",
+     shapes_list,
+     dark_color FROM gtfs.routes WHERE onestop_feed_id = $1;",
                         &[&req_feed_id],
                     )
                     .await;
@@ -330,6 +336,7 @@ pub async fn getroutesperagency(
                                 continuous_pickup: row.get(11),
                                 continuous_drop_off: row.get(12),
                                 shapes_list: row.get(13),
+                                dark_color: row.get(14),
                             }));
 
                         let json_string = to_string(&json!(result)).unwrap();
@@ -355,10 +362,846 @@ pub async fn getroutesperagency(
     }
 }
 
+#[derive(serde::Serialize)]
+struct AgencyPostgres {
+    onestop_feed_id: String,
+    agency_id: String,
+    agency_name: String,
+    agency_url: Option<String>,
+    agency_timezone: Option<String>,
+    agency_lang: Option<String>,
+    agency_phone: Option<String>,
+    agency_fare_url: Option<String>,
+    agency_email: Option<String>,
+    onestop_operator_id: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct FeedInfoPostgres {
+    onestop_feed_id: String,
+    feed_publisher_name: Option<String>,
+    feed_publisher_url: Option<String>,
+    feed_lang: Option<String>,
+    default_lang: Option<String>,
+    feed_start_date: Option<String>,
+    feed_end_date: Option<String>,
+    feed_version: Option<String>,
+    feed_contact_email: Option<String>,
+    feed_contact_url: Option<String>,
+}
+
+//agencies of a static feed and its feed_info, agency_id is optional
+#[actix_web::get("/getagency")]
+pub async fn getagency(
+    pool: web::Data<bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let mut client = pool.get().await;
+
+    if client.is_ok() {
+        let mut client = client.unwrap();
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        let req_feed_id = match qs.get("feed_id") {
+            Some(feed_id) => feed_id,
+            None => {
+                return HttpResponse::BadRequest()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("No feed_id specified")
+            }
+        };
+        let req_agency_id = qs.get("agency_id");
+
+        let agencies = client.query(
+            "SELECT onestop_feed_id, agency_id, agency_name, agency_url, agency_timezone, agency_lang,
+            agency_phone, agency_fare_url, agency_email, onestop_operator_id
+            FROM gtfs.agencies WHERE onestop_feed_id = $1 AND ($2::text IS NULL OR agency_id = $2);",
+            &[&req_feed_id, &req_agency_id],
+        );
+
+        let feed_info = client.query(
+            "SELECT onestop_feed_id, feed_publisher_name, feed_publisher_url, feed_lang, default_lang,
+            feed_start_date, feed_end_date, feed_version, feed_contact_email, feed_contact_url
+            FROM gtfs.feed_info WHERE onestop_feed_id = $1;",
+            &[&req_feed_id],
+        );
+
+        match join!(agencies, feed_info).await {
+            (Ok(agencies), Ok(feed_info)) => {
+                let agencies_result: Vec<AgencyPostgres> = agencies
+                    .iter()
+                    .map(|row| AgencyPostgres {
+                        onestop_feed_id: row.get(0),
+                        agency_id: row.get(1),
+                        agency_name: row.get(2),
+                        agency_url: row.get(3),
+                        agency_timezone: row.get(4),
+                        agency_lang: row.get(5),
+                        agency_phone: row.get(6),
+                        agency_fare_url: row.get(7),
+                        agency_email: row.get(8),
+                        onestop_operator_id: row.get(9),
+                    })
+                    .collect();
+
+                let feed_info_result: Option<FeedInfoPostgres> =
+                    feed_info.first().map(|row| FeedInfoPostgres {
+                        onestop_feed_id: row.get(0),
+                        feed_publisher_name: row.get(1),
+                        feed_publisher_url: row.get(2),
+                        feed_lang: row.get(3),
+                        default_lang: row.get(4),
+                        feed_start_date: row
+                            .get::<_, Option<NaiveDate>>(5)
+                            .map(|date| date.format("%Y-%m-%d").to_string()),
+                        feed_end_date: row
+                            .get::<_, Option<NaiveDate>>(6)
+                            .map(|date| date.format("%Y-%m-%d").to_string()),
+                        feed_version: row.get(7),
+                        feed_contact_email: row.get(8),
+                        feed_contact_url: row.get(9),
+                    });
+
+                HttpResponse::Ok()
+                    .insert_header(("Content-Type", "application/json"))
+                    .body(
+                        to_string(&json!({
+                            "agencies": agencies_result,
+                            "feed_info": feed_info_result
+                        }))
+                        .unwrap(),
+                    )
+            }
+            _ => HttpResponse::InternalServerError()
+                .insert_header(("Content-Type", "text/plain"))
+                .body("Postgres Error"),
+        }
+    } else {
+        HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain"))
+            .body("Couldn't connect to pool")
+    }
+}
+
+#[derive(serde::Serialize)]
+struct StationNodePostgres {
+    stop_id: String,
+    name: String,
+    location_type: Option<i16>,
+    parent_station: Option<String>,
+    level_id: Option<String>,
+    platform_code: Option<String>,
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(serde::Serialize)]
+struct LevelPostgres {
+    level_id: String,
+    level_index: f64,
+    level_name: Option<String>,
+}
+
+//one direction of a pathway, bidirectional pathways show up twice
+#[derive(serde::Serialize)]
+struct StationConnection {
+    pathway_id: String,
+    from_stop_id: String,
+    to_stop_id: String,
+    pathway_mode: i16,
+    mode_name: &'static str,
+    length: Option<f64>,
+    traversal_time: Option<i32>,
+    //true when the feed has no traversal_time and it was worked out from the length or stairs
+    traversal_time_estimated: bool,
+    stair_count: Option<i32>,
+    max_slope: Option<f64>,
+    min_width: Option<f64>,
+    signposted_as: Option<String>,
+    step_free: bool,
+}
+
+#[derive(serde::Serialize)]
+struct TransferPostgres {
+    from_stop_id: Option<String>,
+    to_stop_id: Option<String>,
+    from_route_id: Option<String>,
+    to_route_id: Option<String>,
+    from_trip_id: Option<String>,
+    to_trip_id: Option<String>,
+    transfer_type: i16,
+    min_transfer_time: Option<i32>,
+}
+
+fn pathway_mode_name(pathway_mode: i16) -> &'static str {
+    match pathway_mode {
+        1 => "walkway",
+        2 => "stairs",
+        3 => "moving_sidewalk",
+        4 => "escalator",
+        5 => "elevator",
+        6 => "fare_gate",
+        7 => "exit_gate",
+        _ => "unknown",
+    }
+}
+
+//rough walking speeds for feeds that give a length but no traversal_time
+fn estimate_traversal_time(
+    pathway_mode: i16,
+    length: Option<f64>,
+    stair_count: Option<i32>,
+) -> Option<i32> {
+    match (pathway_mode, length, stair_count) {
+        (2, _, Some(stair_count)) => Some(stair_count.abs()),
+        (2, Some(length), None) => Some((length / 0.5).ceil() as i32),
+        (4, Some(length), _) => Some((length / 0.5).ceil() as i32),
+        (5, _, _) => Some(60),
+        (6, _, _) | (7, _, _) => Some(
+            length
+                .map(|length| (length / 1.3).ceil() as i32)
+                .unwrap_or(5),
+        ),
+        (_, Some(length), _) => Some((length / 1.3).ceil() as i32),
+        _ => None,
+    }
+}
+
+//entrances, platforms, elevators and walking connections inside a station, from pathways.txt and levels.txt
+#[actix_web::get("/stationnavigation")]
+pub async fn stationnavigation(
+    pool: web::Data<bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let mut client = pool.get().await;
+
+    if client.is_ok() {
+        let mut client = client.unwrap();
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        let (req_feed_id, req_stop_id) = match (qs.get("feed_id"), qs.get("stop_id")) {
+            (Some(feed_id), Some(stop_id)) => (feed_id, stop_id),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("feed_id and stop_id are required")
+            }
+        };
+
+        //a platform id resolves to the station it belongs to
+        let station_result = client
+            .query(
+                "SELECT stations.gtfs_id, stations.name, stations.children_ids
+                FROM gtfs.stops AS stops
+                JOIN gtfs.stops AS stations ON stations.onestop_feed_id = stops.onestop_feed_id
+                AND stations.gtfs_id = CASE WHEN stops.location_type = 1 OR stops.parent_station IS NULL
+                THEN stops.gtfs_id ELSE stops.parent_station END
+                WHERE stops.onestop_feed_id = $1 AND stops.gtfs_id = $2;",
+                &[&req_feed_id, &req_stop_id],
+            )
+            .await;
+
+        let station_row = match station_result {
+            Ok(rows) => match rows.into_iter().next() {
+                Some(row) => row,
+                None => {
+                    return HttpResponse::NotFound()
+                        .insert_header(("Content-Type", "text/plain"))
+                        .body("Stop not found")
+                }
+            },
+            Err(e) => {
+                println!("{:?}", e);
+                return HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error");
+            }
+        };
+
+        let station_id: String = station_row.get(0);
+        let station_name: String = station_row.get(1);
+        let mut member_ids: Vec<String> = station_row
+            .get::<_, Option<Vec<String>>>(2)
+            .unwrap_or_default();
+        member_ids.push(station_id.to_owned());
+
+        //children of children are boarding areas on a platform
+        let nodes_result = client
+            .query(
+                "SELECT gtfs_id, name, location_type, parent_station, level_id, platform_code,
+                ST_Y(point), ST_X(point)
+                FROM gtfs.stops WHERE onestop_feed_id = $1
+                AND (gtfs_id = ANY($2) OR parent_station = ANY($2)) AND gtfs_id != $3;",
+                &[&req_feed_id, &member_ids, &station_id],
+            )
+            .await;
+
+        let nodes: Vec<StationNodePostgres> = match nodes_result {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| StationNodePostgres {
+                    stop_id: row.get(0),
+                    name: row.get(1),
+                    location_type: row.get(2),
+                    parent_station: row.get(3),
+                    level_id: row.get(4),
+                    platform_code: row.get(5),
+                    lat: row.get(6),
+                    lon: row.get(7),
+                })
+                .collect(),
+            Err(e) => {
+                println!("{:?}", e);
+                return HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error");
+            }
+        };
+
+        let mut node_ids: Vec<String> = nodes.iter().map(|node| node.stop_id.to_owned()).collect();
+        node_ids.push(station_id.to_owned());
+
+        let level_ids: Vec<String> = nodes
+            .iter()
+            .filter_map(|node| node.level_id.to_owned())
+            .collect();
+
+        let pathways = client.query(
+            "SELECT pathway_id, from_stop_id, to_stop_id, pathway_mode, is_bidirectional, length,
+            traversal_time, stair_count, max_slope, min_width, signposted_as, reversed_signposted_as
+            FROM gtfs.pathways WHERE onestop_feed_id = $1
+            AND (from_stop_id = ANY($2) OR to_stop_id = ANY($2));",
+            &[&req_feed_id, &node_ids],
+        );
+
+        let levels = client.query(
+            "SELECT level_id, level_index, level_name FROM gtfs.levels
+            WHERE onestop_feed_id = $1 AND level_id = ANY($2) ORDER BY level_index;",
+            &[&req_feed_id, &level_ids],
+        );
+
+        let transfers = client.query(
+            "SELECT from_stop_id, to_stop_id, from_route_id, to_route_id, from_trip_id, to_trip_id,
+            transfer_type, min_transfer_time
+            FROM gtfs.transfers WHERE onestop_feed_id = $1
+            AND (from_stop_id = ANY($2) OR to_stop_id = ANY($2));",
+            &[&req_feed_id, &node_ids],
+        );
+
+        let (pathways, levels, transfers) = match join!(pathways, levels, transfers).await {
+            (Ok(pathways), Ok(levels), Ok(transfers)) => (pathways, levels, transfers),
+            _ => {
+                return HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error")
+            }
+        };
+
+        let mut connections: Vec<StationConnection> = vec![];
+
+        for row in pathways.iter() {
+            let pathway_id: String = row.get(0);
+            let from_stop_id: String = row.get(1);
+            let to_stop_id: String = row.get(2);
+            let pathway_mode: i16 = row.get(3);
+            let is_bidirectional: bool = row.get(4);
+            let length: Option<f64> = row.get(5);
+            let traversal_time: Option<i32> = row.get(6);
+            let stair_count: Option<i32> = row.get(7);
+            let max_slope: Option<f64> = row.get(8);
+            let min_width: Option<f64> = row.get(9);
+            let signposted_as: Option<String> = row.get(10);
+            let reversed_signposted_as: Option<String> = row.get(11);
+
+            let (traversal_time, traversal_time_estimated) = match traversal_time {
+                Some(traversal_time) => (Some(traversal_time), false),
+                None => (
+                    estimate_traversal_time(pathway_mode, length, stair_count),
+                    true,
+                ),
+            };
+
+            //stairs and escalators can't be used with a wheelchair
+            let step_free = pathway_mode != 2 && pathway_mode != 4;
+
+            let mut directions = vec![(
+                from_stop_id.to_owned(),
+                to_stop_id.to_owned(),
+                signposted_as,
+            )];
+
+            if is_bidirectional {
+                directions.push((to_stop_id, from_stop_id, reversed_signposted_as));
+            }
+
+            for (from_stop_id, to_stop_id, signposted_as) in directions {
+                connections.push(StationConnection {
+                    pathway_id: pathway_id.to_owned(),
+                    from_stop_id: from_stop_id,
+                    to_stop_id: to_stop_id,
+                    pathway_mode: pathway_mode,
+                    mode_name: pathway_mode_name(pathway_mode),
+                    length: length,
+                    traversal_time: traversal_time,
+                    traversal_time_estimated: traversal_time_estimated,
+                    stair_count: stair_count,
+                    max_slope: max_slope,
+                    min_width: min_width,
+                    signposted_as: signposted_as,
+                    step_free: step_free,
+                });
+            }
+        }
+
+        //node -> indices into connections leaving it
+        let mut graph: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (index, connection) in connections.iter().enumerate() {
+            graph
+                .entry(connection.from_stop_id.to_owned())
+                .or_insert(vec![])
+                .push(index);
+        }
+
+        let levels: Vec<LevelPostgres> = levels
+            .iter()
+            .map(|row| LevelPostgres {
+                level_id: row.get(0),
+                level_index: row.get(1),
+                level_name: row.get(2),
+            })
+            .collect();
+
+        let transfers: Vec<TransferPostgres> = transfers
+            .iter()
+            .map(|row| TransferPostgres {
+                from_stop_id: row.get(0),
+                to_stop_id: row.get(1),
+                from_route_id: row.get(2),
+                to_route_id: row.get(3),
+                from_trip_id: row.get(4),
+                to_trip_id: row.get(5),
+                transfer_type: row.get(6),
+                min_transfer_time: row.get(7),
+            })
+            .collect();
+
+        let elevators: Vec<&StationConnection> = connections
+            .iter()
+            .filter(|connection| connection.pathway_mode == 5)
+            .collect();
+
+        let entrances: Vec<&StationNodePostgres> = nodes
+            .iter()
+            .filter(|node| node.location_type == Some(2))
+            .collect();
+
+        let platforms: Vec<&StationNodePostgres> = nodes
+            .iter()
+            .filter(|node| node.location_type.unwrap_or(0) == 0)
+            .collect();
+
+        let other_nodes: Vec<&StationNodePostgres> = nodes
+            .iter()
+            .filter(|node| node.location_type == Some(3) || node.location_type == Some(4))
+            .collect();
+
+        let json_string = to_string(&json!({
+            "station": {
+                "stop_id": station_id,
+                "name": station_name
+            },
+            "levels": levels,
+            "entrances": entrances,
+            "platforms": platforms,
+            "nodes": other_nodes,
+            "elevators": elevators,
+            "connections": connections,
+            "graph": graph,
+            "transfers": transfers
+        }))
+        .unwrap();
+
+        HttpResponse::Ok()
+            .insert_header(("Content-Type", "application/json"))
+            .body(json_string)
+    } else {
+        HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain"))
+            .body("Couldn't connect to pool")
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FareAttributePostgres {
+    fare_id: String,
+    price: f64,
+    currency_type: String,
+    payment_method: i16,
+    transfers: Option<i16>,
+    agency_id: Option<String>,
+    transfer_duration: Option<i32>,
+}
+
+#[derive(serde::Serialize)]
+struct FareProductPostgres {
+    leg_group_id: Option<String>,
+    fare_product_id: String,
+    fare_product_name: Option<String>,
+    fare_media_id: Option<String>,
+    amount: f64,
+    currency: String,
+}
+
+#[derive(serde::Serialize)]
+struct FareTransferRulePostgres {
+    from_leg_group_id: Option<String>,
+    to_leg_group_id: Option<String>,
+    transfer_count: Option<i32>,
+    duration_limit: Option<i32>,
+    duration_limit_type: Option<i16>,
+    fare_transfer_type: i16,
+    fare_product_id: Option<String>,
+}
+
+struct FareLegRulePostgres {
+    leg_group_id: Option<String>,
+    network_id: Option<String>,
+    from_area_id: Option<String>,
+    to_area_id: Option<String>,
+    fare_product_id: String,
+    rule_priority: i32,
+}
+
+//fares v2 matching for one field: a rule naming one of the candidates wins,
+//an empty value only matches when no rule names any of the candidates
+fn fare_leg_field_matches(
+    value: &Option<String>,
+    candidates: &[String],
+    any_rule_names_candidate: bool,
+) -> bool {
+    match (value, any_rule_names_candidate) {
+        (Some(value), true) => candidates.contains(value),
+        (None, false) => true,
+        _ => false,
+    }
+}
+
+//what a ride on a route from one stop to another costs, using fares v1 and v2 when the feed has them
+#[actix_web::get("/fares")]
+pub async fn fares(
+    pool: web::Data<bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let mut client = pool.get().await;
+
+    if client.is_ok() {
+        let mut client = client.unwrap();
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        let (req_feed_id, req_route_id, req_from_stop_id, req_to_stop_id) = match (
+            qs.get("feed_id"),
+            qs.get("route_id"),
+            qs.get("from_stop_id"),
+            qs.get("to_stop_id"),
+        ) {
+            (Some(feed_id), Some(route_id), Some(from_stop_id), Some(to_stop_id)) => {
+                (feed_id, route_id, from_stop_id, to_stop_id)
+            }
+            _ => {
+                return HttpResponse::BadRequest()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("feed_id, route_id, from_stop_id and to_stop_id are required")
+            }
+        };
+
+        let stop_ids = vec![req_from_stop_id.to_string(), req_to_stop_id.to_string()];
+
+        let stops = client.query(
+            "SELECT gtfs_id, zone_id, parent_station FROM gtfs.stops
+            WHERE onestop_feed_id = $1 AND gtfs_id = ANY($2);",
+            &[&req_feed_id, &stop_ids],
+        );
+
+        let fare_attributes = client.query(
+            "SELECT fare_id, price, currency_type, payment_method, transfers, agency_id, transfer_duration
+            FROM gtfs.fare_attributes WHERE onestop_feed_id = $1;",
+            &[&req_feed_id],
+        );
+
+        let fare_rules = client.query(
+            "SELECT fare_id, route_id, origin_id, destination_id, contains_id
+            FROM gtfs.fare_rules WHERE onestop_feed_id = $1;",
+            &[&req_feed_id],
+        );
+
+        let networks = client.query(
+            "SELECT network_id FROM gtfs.route_networks WHERE onestop_feed_id = $1 AND route_id = $2;",
+            &[&req_feed_id, &req_route_id],
+        );
+
+        let fare_leg_rules = client.query(
+            "SELECT leg_group_id, network_id, from_area_id, to_area_id, fare_product_id, COALESCE(rule_priority, 0)
+            FROM gtfs.fare_leg_rules WHERE onestop_feed_id = $1;",
+            &[&req_feed_id],
+        );
+
+        let (stops, fare_attributes, fare_rules, networks, fare_leg_rules) =
+            match join!(stops, fare_attributes, fare_rules, networks, fare_leg_rules).await {
+                (
+                    Ok(stops),
+                    Ok(fare_attributes),
+                    Ok(fare_rules),
+                    Ok(networks),
+                    Ok(fare_leg_rules),
+                ) => (stops, fare_attributes, fare_rules, networks, fare_leg_rules),
+                _ => {
+                    return HttpResponse::InternalServerError()
+                        .insert_header(("Content-Type", "text/plain"))
+                        .body("Postgres Error")
+                }
+            };
+
+        let zone_of = |stop_id: &str| -> Option<String> {
+            stops
+                .iter()
+                .find(|row| row.get::<_, String>(0) == stop_id)
+                .and_then(|row| row.get::<_, Option<String>>(1))
+        };
+
+        let from_zone = zone_of(req_from_stop_id);
+        let to_zone = zone_of(req_to_stop_id);
+
+        //fares v1, a feed without fare_rules.txt charges its fare everywhere
+        let matching_fare_ids: Option<Vec<String>> = match fare_rules.len() {
+            0 => None,
+            _ => Some(
+                fare_rules
+                    .iter()
+                    .filter(|row| {
+                        let route_id: Option<String> = row.get(1);
+                        let origin_id: Option<String> = row.get(2);
+                        let destination_id: Option<String> = row.get(3);
+                        let contains_id: Option<String> = row.get(4);
+
+                        route_id.map(|route_id| route_id == req_route_id).unwrap_or(true)
+                            && origin_id.map(|origin_id| Some(origin_id) == from_zone).unwrap_or(true)
+                            && destination_id
+                                .map(|destination_id| Some(destination_id) == to_zone)
+                                .unwrap_or(true)
+                            //only the two ends are known here, not every zone the ride passes through
+                            && contains_id
+                                .map(|contains_id| {
+                                    Some(&contains_id) == from_zone.as_ref()
+                                        || Some(&contains_id) == to_zone.as_ref()
+                                })
+                                .unwrap_or(true)
+                    })
+                    .map(|row| row.get::<_, String>(0))
+                    .collect(),
+            ),
+        };
+
+        let fares_v1: Vec<FareAttributePostgres> = fare_attributes
+            .iter()
+            .filter(|row| match &matching_fare_ids {
+                Some(matching_fare_ids) => matching_fare_ids.contains(&row.get::<_, String>(0)),
+                None => true,
+            })
+            .map(|row| FareAttributePostgres {
+                fare_id: row.get(0),
+                price: row.get(1),
+                currency_type: row.get(2),
+                payment_method: row.get(3),
+                transfers: row.get(4),
+                agency_id: row.get(5),
+                transfer_duration: row.get(6),
+            })
+            .collect();
+
+        //fares v2, areas of a platform also include the areas of its station
+        let mut area_stop_ids = stop_ids.clone();
+        for row in stops.iter() {
+            if let Some(parent_station) = row.get::<_, Option<String>>(2) {
+                area_stop_ids.push(parent_station);
+            }
+        }
+
+        let stop_areas = match client
+            .query(
+                "SELECT stops.gtfs_id, stop_areas.area_id FROM gtfs.stop_areas AS stop_areas
+                JOIN gtfs.stops AS stops ON stops.onestop_feed_id = stop_areas.onestop_feed_id
+                AND (stops.gtfs_id = stop_areas.stop_id OR stops.parent_station = stop_areas.stop_id)
+                WHERE stop_areas.onestop_feed_id = $1 AND stop_areas.stop_id = ANY($2)
+                AND stops.gtfs_id = ANY($3);",
+                &[&req_feed_id, &area_stop_ids, &stop_ids],
+            )
+            .await
+        {
+            Ok(stop_areas) => stop_areas,
+            Err(e) => {
+                println!("{:?}", e);
+                return HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error");
+            }
+        };
+
+        let areas_of = |stop_id: &str| -> Vec<String> {
+            stop_areas
+                .iter()
+                .filter(|row| row.get::<_, String>(0) == stop_id)
+                .map(|row| row.get::<_, String>(1))
+                .collect()
+        };
+
+        let from_areas = areas_of(req_from_stop_id);
+        let to_areas = areas_of(req_to_stop_id);
+        let network_ids: Vec<String> = networks.iter().map(|row| row.get(0)).collect();
+
+        let fare_leg_rules: Vec<FareLegRulePostgres> = fare_leg_rules
+            .iter()
+            .map(|row| FareLegRulePostgres {
+                leg_group_id: row.get(0),
+                network_id: row.get(1),
+                from_area_id: row.get(2),
+                to_area_id: row.get(3),
+                fare_product_id: row.get(4),
+                rule_priority: row.get(5),
+            })
+            .collect();
+
+        let network_named = fare_leg_rules.iter().any(|rule| {
+            rule.network_id
+                .as_ref()
+                .map(|network_id| network_ids.contains(network_id))
+                .unwrap_or(false)
+        });
+        let from_area_named = fare_leg_rules.iter().any(|rule| {
+            rule.from_area_id
+                .as_ref()
+                .map(|area_id| from_areas.contains(area_id))
+                .unwrap_or(false)
+        });
+        let to_area_named = fare_leg_rules.iter().any(|rule| {
+            rule.to_area_id
+                .as_ref()
+                .map(|area_id| to_areas.contains(area_id))
+                .unwrap_or(false)
+        });
+
+        let matching_leg_rules: Vec<&FareLegRulePostgres> = fare_leg_rules
+            .iter()
+            .filter(|rule| {
+                fare_leg_field_matches(&rule.network_id, &network_ids, network_named)
+                    && fare_leg_field_matches(&rule.from_area_id, &from_areas, from_area_named)
+                    && fare_leg_field_matches(&rule.to_area_id, &to_areas, to_area_named)
+            })
+            .collect();
+
+        //when priorities are given only the highest matching ones apply
+        let top_priority = matching_leg_rules
+            .iter()
+            .map(|rule| rule.rule_priority)
+            .max()
+            .unwrap_or(0);
+
+        let matching_leg_rules: Vec<&FareLegRulePostgres> = matching_leg_rules
+            .into_iter()
+            .filter(|rule| rule.rule_priority == top_priority)
+            .collect();
+
+        let product_ids: Vec<String> = matching_leg_rules
+            .iter()
+            .map(|rule| rule.fare_product_id.to_owned())
+            .collect();
+        let leg_group_ids: Vec<String> = matching_leg_rules
+            .iter()
+            .filter_map(|rule| rule.leg_group_id.to_owned())
+            .collect();
+
+        let fare_products = client.query(
+            "SELECT fare_product_id, fare_product_name, fare_media_id, amount, currency
+            FROM gtfs.fare_products WHERE onestop_feed_id = $1 AND fare_product_id = ANY($2);",
+            &[&req_feed_id, &product_ids],
+        );
+
+        let fare_transfer_rules = client.query(
+            "SELECT from_leg_group_id, to_leg_group_id, transfer_count, duration_limit,
+            duration_limit_type, fare_transfer_type, fare_product_id
+            FROM gtfs.fare_transfer_rules WHERE onestop_feed_id = $1
+            AND (from_leg_group_id = ANY($2) OR to_leg_group_id = ANY($2));",
+            &[&req_feed_id, &leg_group_ids],
+        );
+
+        let (fare_products, fare_transfer_rules) = match join!(fare_products, fare_transfer_rules)
+            .await
+        {
+            (Ok(fare_products), Ok(fare_transfer_rules)) => (fare_products, fare_transfer_rules),
+            _ => {
+                return HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error")
+            }
+        };
+
+        let mut fares_v2: Vec<FareProductPostgres> = vec![];
+
+        for rule in matching_leg_rules.iter() {
+            for row in fare_products
+                .iter()
+                .filter(|row| row.get::<_, String>(0) == rule.fare_product_id)
+            {
+                fares_v2.push(FareProductPostgres {
+                    leg_group_id: rule.leg_group_id.to_owned(),
+                    fare_product_id: row.get(0),
+                    fare_product_name: row.get(1),
+                    fare_media_id: row.get(2),
+                    amount: row.get(3),
+                    currency: row.get(4),
+                });
+            }
+        }
+
+        let transfer_rules: Vec<FareTransferRulePostgres> = fare_transfer_rules
+            .iter()
+            .map(|row| FareTransferRulePostgres {
+                from_leg_group_id: row.get(0),
+                to_leg_group_id: row.get(1),
+                transfer_count: row.get(2),
+                duration_limit: row.get(3),
+                duration_limit_type: row.get(4),
+                fare_transfer_type: row.get(5),
+                fare_product_id: row.get(6),
+            })
+            .collect();
+
+        let json_string = to_string(&json!({
+            "fares_v1": fares_v1,
+            "fares_v2": fares_v2,
+            "transfer_rules": transfer_rules
+        }))
+        .unwrap();
+
+        HttpResponse::Ok()
+            .insert_header(("Content-Type", "application/json"))
+            .body(json_string)
+    } else {
+        HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain"))
+            .body("Couldn't connect to pool")
+    }
+}
+
 #[derive(serde::Serialize)]
 struct GtfsIngestError<'a> {
     onestop_feed_id: &'a str,
+    stage: Option<&'a str>,
     error: &'a str,
+    recorded_at: Option<String>,
 }
 
 #[actix_web::get("/gtfsingesterrors")]
@@ -371,16 +1214,35 @@ pub async fn gtfsingesterrors(
     if client.is_ok() {
         let mut client = client.unwrap();
 
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        //both filters are optional, leaving them out returns every error
+        let req_feed_id = qs.get("feed_id");
+        let req_stage = qs.get("stage");
+
         let postgresresult = client
-            .query("SELECT onestop_feed_id, error FROM gtfs.gtfs_errors;", &[])
+            .query(
+                "SELECT onestop_feed_id, stage, error, recorded_at FROM gtfs.gtfs_errors
+                WHERE ($1::text IS NULL OR onestop_feed_id = $1)
+                AND ($2::text IS NULL OR stage = $2)
+                ORDER BY onestop_feed_id, recorded_at;",
+                &[&req_feed_id, &req_stage],
+            )
             .await;
 
         match postgresresult {
             Ok(postgresresult) => {
                 let result: Vec<GtfsIngestError> =
-                    Vec::from_iter(postgresresult.iter().map(|row: &Row| GtfsIngestError {
-                        onestop_feed_id: row.get(0),
-                        error: row.get(1),
+                    Vec::from_iter(postgresresult.iter().map(|row: &Row| {
+                        GtfsIngestError {
+                            onestop_feed_id: row.get(0),
+                            stage: row.get(1),
+                            error: row.get(2),
+                            recorded_at: row
+                                .get::<_, Option<chrono::DateTime<chrono::Utc>>>(3)
+                                .map(|recorded_at| recorded_at.to_rfc3339()),
+                        }
                     }));
                 let json_string = to_string(&json!(result)).unwrap();
 
@@ -403,6 +1265,750 @@ pub async fn gtfsingesterrors(
     }
 }
 
+#[derive(serde::Serialize)]
+struct DeparturePostgres {
+    trip_id: String,
+    service_date: String,
+    stop_id: String,
+    stop_sequence: i32,
+    arrival_time: Option<i64>,
+    departure_time: Option<i64>,
+    scheduled_arrival: Option<String>,
+    scheduled_departure: Option<String>,
+    stop_headsign: Option<String>,
+    trip_headsign: Option<String>,
+    trip_short_name: Option<String>,
+    stop_headsigns: Option<Vec<Option<String>>>,
+    route_id: String,
+    route_short_name: String,
+    route_long_name: String,
+    route_type: i16,
+    color: Option<String>,
+    text_color: Option<String>,
+    //the feed left this stop untimed, the times were estimated from the timepoints around it
+    interpolated: bool,
+    //only set for trips from frequencies.txt, exact_times false means the times are an estimate from the headway
+    headway_secs: Option<i32>,
+    exact_times: Option<bool>,
+}
+
+const DEPARTURE_COLUMNS: &str = "stoptimes.trip_id, stoptimes.stop_id, stoptimes.stop_sequence,
+    stoptimes.arrival_time, stoptimes.departure_time, stoptimes.stop_headsign,
+    trips.trip_headsign, trips.trip_short_name, trips.stop_headsigns,
+    routes.route_id, routes.short_name, routes.long_name, routes.route_type,
+    routes.color, routes.text_color, stoptimes.interpolated";
+
+const DEPARTURE_JOINS: &str = "FROM gtfs.stoptimes AS stoptimes
+    JOIN gtfs.trips AS trips ON trips.onestop_feed_id = stoptimes.onestop_feed_id
+    AND trips.trip_id = stoptimes.trip_id
+    JOIN gtfs.routes AS routes ON routes.onestop_feed_id = trips.onestop_feed_id
+    AND routes.route_id = trips.route_id
+    JOIN gtfs.service_days AS service_days ON service_days.onestop_feed_id = trips.onestop_feed_id
+    AND service_days.service_id = trips.service_id";
+
+//row is DEPARTURE_COLUMNS, times are passed separately so frequency trips can shift the template times
+fn departure_from_row(
+    row: &Row,
+    timezone: &Tz,
+    stop_timezone: &Tz,
+    date: &NaiveDate,
+    arrival_time: Option<i64>,
+    departure_time: Option<i64>,
+    frequency: Option<(i32, bool)>,
+) -> DeparturePostgres {
+    DeparturePostgres {
+        trip_id: row.get(0),
+        service_date: date.format("%Y-%m-%d").to_string(),
+        stop_id: row.get(1),
+        stop_sequence: row.get(2),
+        arrival_time: arrival_time,
+        departure_time: departure_time,
+        scheduled_arrival: render_gtfs_time(timezone, stop_timezone, date, arrival_time),
+        scheduled_departure: render_gtfs_time(timezone, stop_timezone, date, departure_time),
+        stop_headsign: row.get(5),
+        trip_headsign: row.get(6),
+        trip_short_name: row.get(7),
+        stop_headsigns: row.get(8),
+        route_id: row.get(9),
+        route_short_name: row.get(10),
+        route_long_name: row.get(11),
+        route_type: row.get(12),
+        color: row.get(13),
+        text_color: row.get(14),
+        interpolated: row.get(15),
+        headway_secs: frequency.map(|(headway_secs, _)| headway_secs),
+        exact_times: frequency.map(|(_, exact_times)| exact_times),
+    }
+}
+
+//stop_times are counted from the service day in the agency timezone, the stop timezone only changes how the instant is shown
+fn render_gtfs_time(
+    timezone: &Tz,
+    stop_timezone: &Tz,
+    service_date: &NaiveDate,
+    seconds: Option<i64>,
+) -> Option<String> {
+    seconds
+        .and_then(|seconds| gtfs_time::gtfs_time_to_utc(timezone, *service_date, seconds))
+        .map(|time| time.with_timezone(stop_timezone).to_rfc3339())
+}
+
+//given a stop, return the scheduled departures for a date and time window
+#[actix_web::get("/departures")]
+pub async fn departures(
+    pool: web::Data<bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let mut client = pool.get().await;
+
+    if client.is_ok() {
+        let mut client = client.unwrap();
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        let (req_feed_id, req_stop_id) = match (qs.get("feed_id"), qs.get("stop_id")) {
+            (Some(feed_id), Some(stop_id)) => (feed_id, stop_id),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("feed_id and stop_id are required")
+            }
+        };
+
+        //the stop itself and every platform inside of it if it's a station
+        let stop_result = client
+            .query(
+                "SELECT stops.gtfs_id, stops.children_ids,
+                COALESCE(stops.timezone, parents.timezone, static_feeds.default_timezone),
+                static_feeds.default_timezone
+                FROM gtfs.stops AS stops
+                LEFT JOIN gtfs.stops AS parents ON parents.onestop_feed_id = stops.onestop_feed_id
+                AND parents.gtfs_id = stops.parent_station
+                LEFT JOIN gtfs.static_feeds AS static_feeds ON static_feeds.onestop_feed_id = stops.onestop_feed_id
+                WHERE stops.onestop_feed_id = $1 AND stops.gtfs_id = $2;",
+                &[&req_feed_id, &req_stop_id],
+            )
+            .await;
+
+        let stop_row = match stop_result {
+            Ok(rows) => match rows.into_iter().next() {
+                Some(row) => row,
+                None => {
+                    return HttpResponse::NotFound()
+                        .insert_header(("Content-Type", "text/plain"))
+                        .body("Stop not found")
+                }
+            },
+            Err(e) => {
+                println!("{:?}", e);
+                return HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error");
+            }
+        };
+
+        let mut stop_ids: Vec<String> = vec![stop_row.get(0)];
+        let children_ids: Option<Vec<String>> = stop_row.get(1);
+        if let Some(children_ids) = children_ids {
+            stop_ids.extend(children_ids);
+        }
+
+        let stop_timezone_name: Option<String> = stop_row.get(2);
+        let timezone_name: Option<String> = stop_row.get(3);
+
+        //service days, the date and time asked for and stop_times are all in the agency timezone
+        let timezone: Tz = gtfs_time::parse_timezone(timezone_name.as_deref())
+            .or(gtfs_time::parse_timezone(stop_timezone_name.as_deref()))
+            .unwrap_or(Tz::UTC);
+        let stop_timezone: Tz =
+            gtfs_time::parse_timezone(stop_timezone_name.as_deref()).unwrap_or(timezone);
+
+        let now = chrono::Utc::now().with_timezone(&timezone);
+
+        let service_date = match qs.get("date") {
+            Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                Ok(date) => date,
+                Err(_) => {
+                    return HttpResponse::BadRequest()
+                        .insert_header(("Content-Type", "text/plain"))
+                        .body("date must be YYYY-MM-DD")
+                }
+            },
+            None => now.date_naive(),
+        };
+
+        let start_seconds: i64 = match qs.get("time") {
+            Some(time) => match NaiveTime::parse_from_str(time, "%H:%M:%S")
+                .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+            {
+                Ok(time) => time.num_seconds_from_midnight() as i64,
+                Err(_) => {
+                    return HttpResponse::BadRequest()
+                        .insert_header(("Content-Type", "text/plain"))
+                        .body("time must be HH:MM or HH:MM:SS")
+                }
+            },
+            None => gtfs_time::default_start_time(
+                &timezone,
+                service_date,
+                now.with_timezone(&chrono::Utc),
+            ),
+        };
+
+        let window_minutes: i64 = qs
+            .get("window_minutes")
+            .and_then(|window| window.parse::<i64>().ok())
+            .unwrap_or(60)
+            .clamp(1, 24 * 60);
+
+        let end_seconds = start_seconds + window_minutes * 60;
+
+        //seconds relative to the requested service day, used for ordering both days together
+        let mut result: Vec<(i64, DeparturePostgres)> = vec![];
+
+        //trips of yesterday's service day run past midnight with times over 24:00:00
+        for (date, offset) in [
+            (service_date.pred_opt().unwrap_or(service_date), 86400),
+            (service_date, 0),
+        ] {
+            let scheduled = client.query(
+                format!(
+                    "SELECT {DEPARTURE_COLUMNS} {DEPARTURE_JOINS}
+                WHERE stoptimes.onestop_feed_id = $1 AND stoptimes.stop_id = ANY($2)
+                AND $3 = ANY(service_days.active_dates)
+                AND COALESCE(stoptimes.departure_time, stoptimes.arrival_time) >= $4
+                AND COALESCE(stoptimes.departure_time, stoptimes.arrival_time) < $5
+                AND NOT EXISTS (SELECT 1 FROM gtfs.frequencies AS frequencies
+                WHERE frequencies.onestop_feed_id = stoptimes.onestop_feed_id
+                AND frequencies.trip_id = stoptimes.trip_id);"
+                )
+                .as_str(),
+                &[
+                    &req_feed_id,
+                    &stop_ids,
+                    &date,
+                    &(start_seconds + offset),
+                    &(end_seconds + offset),
+                ],
+            );
+
+            //frequency trips store one template run, the last column is when that template leaves its first stop
+            let frequency_based = client.query(
+                format!(
+                    "SELECT {DEPARTURE_COLUMNS},
+                frequencies.start_time, frequencies.end_time, frequencies.headway_secs, frequencies.exact_times,
+                (SELECT MIN(COALESCE(first_stop.departure_time, first_stop.arrival_time))
+                FROM gtfs.stoptimes AS first_stop
+                WHERE first_stop.onestop_feed_id = stoptimes.onestop_feed_id
+                AND first_stop.trip_id = stoptimes.trip_id)
+                {DEPARTURE_JOINS}
+                JOIN gtfs.frequencies AS frequencies ON frequencies.onestop_feed_id = stoptimes.onestop_feed_id
+                AND frequencies.trip_id = stoptimes.trip_id
+                WHERE stoptimes.onestop_feed_id = $1 AND stoptimes.stop_id = ANY($2)
+                AND $3 = ANY(service_days.active_dates);"
+                )
+                .as_str(),
+                &[&req_feed_id, &stop_ids, &date],
+            );
+
+            match join!(scheduled, frequency_based).await {
+                (Ok(scheduled), Ok(frequency_based)) => {
+                    result.extend(scheduled.iter().map(|row| {
+                        let arrival_time: Option<i64> = row.get(3);
+                        let departure_time: Option<i64> = row.get(4);
+
+                        (
+                            departure_time.or(arrival_time).unwrap_or(0) - offset,
+                            departure_from_row(
+                                row,
+                                &timezone,
+                                &stop_timezone,
+                                &date,
+                                arrival_time,
+                                departure_time,
+                                None,
+                            ),
+                        )
+                    }));
+
+                    for row in frequency_based.iter() {
+                        let arrival_time: Option<i64> = row.get(3);
+                        let departure_time: Option<i64> = row.get(4);
+                        let window_start: i32 = row.get(16);
+                        let window_end: i32 = row.get(17);
+                        let headway_secs: i32 = row.get(18);
+                        let exact_times: bool = row.get(19);
+                        let template_start: i64 = row.get::<_, Option<i64>>(20).unwrap_or(0);
+
+                        if headway_secs <= 0 {
+                            continue;
+                        }
+
+                        let mut trip_start = window_start as i64;
+
+                        while trip_start < window_end as i64 {
+                            let shift = trip_start - template_start;
+                            let arrival_time = arrival_time.map(|time| time + shift);
+                            let departure_time = departure_time.map(|time| time + shift);
+
+                            if let Some(seconds) = departure_time.or(arrival_time) {
+                                if seconds >= start_seconds + offset
+                                    && seconds < end_seconds + offset
+                                {
+                                    result.push((
+                                        seconds - offset,
+                                        departure_from_row(
+                                            row,
+                                            &timezone,
+                                            &stop_timezone,
+                                            &date,
+                                            arrival_time,
+                                            departure_time,
+                                            Some((headway_secs, exact_times)),
+                                        ),
+                                    ));
+                                }
+                            }
+
+                            trip_start = trip_start + headway_secs as i64;
+                        }
+                    }
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    println!("{:?}", e);
+                    println!("No results from postgres");
+
+                    return HttpResponse::InternalServerError()
+                        .insert_header(("Content-Type", "text/plain"))
+                        .body("Postgres Error");
+                }
+            }
+        }
+
+        result.sort_by_key(|(seconds, _)| *seconds);
+
+        let result: Vec<DeparturePostgres> =
+            result.into_iter().map(|(_, departure)| departure).collect();
+
+        let json_string = to_string(&json!({
+            "timezone": stop_timezone.name(),
+            "agency_timezone": timezone.name(),
+            "departures": result
+        }))
+        .unwrap();
+
+        HttpResponse::Ok()
+            .insert_header(("Content-Type", "application/json"))
+            .body(json_string)
+    } else {
+        HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain"))
+            .body("Couldn't connect to pool")
+    }
+}
+
+#[derive(serde::Serialize)]
+struct StopPostgres {
+    onestop_feed_id: String,
+    gtfs_id: String,
+    name: String,
+    displayname: String,
+    code: Option<String>,
+    lat: f64,
+    lon: f64,
+    location_type: Option<i16>,
+    parent_station: Option<String>,
+    routes: Option<Vec<String>>,
+    route_types: Option<Vec<i16>>,
+    children_ids: Option<Vec<String>>,
+    children_route_types: Option<Vec<i16>>,
+    hidden: Option<bool>,
+    location_alias: Option<Vec<String>>,
+    distance_m: Option<f64>,
+}
+
+const STOP_COLUMNS: &str = "onestop_feed_id, gtfs_id, name, displayname, code,
+    ST_Y(point), ST_X(point), location_type, parent_station, routes, route_types,
+    children_ids, children_route_types, hidden, location_alias";
+
+fn stop_from_row(row: &Row, distance_m: Option<f64>) -> StopPostgres {
+    StopPostgres {
+        onestop_feed_id: row.get(0),
+        gtfs_id: row.get(1),
+        name: row.get(2),
+        displayname: row.get(3),
+        code: row.get(4),
+        lat: row.get(5),
+        lon: row.get(6),
+        location_type: row.get(7),
+        parent_station: row.get(8),
+        routes: row.get(9),
+        route_types: row.get(10),
+        children_ids: row.get(11),
+        children_route_types: row.get(12),
+        hidden: row.get(13),
+        location_alias: row.get(14),
+        distance_m: distance_m,
+    }
+}
+
+fn parse_f64_param(qs: &QString, name: &str) -> Option<f64> {
+    qs.get(name)
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+}
+
+//same view of stops the busstops and railstops tile functions give, unless hidden stops are asked for
+fn include_hidden(qs: &QString) -> bool {
+    qs.get("include_hidden") == Some("true")
+}
+
+#[actix_web::get("/stopsinbbox")]
+pub async fn stopsinbbox(
+    pool: web::Data<bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let mut client = pool.get().await;
+
+    if client.is_ok() {
+        let mut client = client.unwrap();
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        let (min_lat, min_lon, max_lat, max_lon) = match (
+            parse_f64_param(&qs, "min_lat"),
+            parse_f64_param(&qs, "min_lon"),
+            parse_f64_param(&qs, "max_lat"),
+            parse_f64_param(&qs, "max_lon"),
+        ) {
+            (Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) => {
+                (min_lat, min_lon, max_lat, max_lon)
+            }
+            _ => {
+                return HttpResponse::BadRequest()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("min_lat, min_lon, max_lat and max_lon are required")
+            }
+        };
+
+        let limit: i64 = qs
+            .get("limit")
+            .and_then(|limit| limit.parse::<i64>().ok())
+            .unwrap_or(1000)
+            .clamp(1, 5000);
+
+        let postgresresult = client
+            .query(
+                format!(
+                    "SELECT {STOP_COLUMNS} FROM gtfs.stops
+                WHERE point && ST_MakeEnvelope($1, $2, $3, $4, 4326)
+                AND ($5 OR hidden = false)
+                LIMIT $6;"
+                )
+                .as_str(),
+                &[
+                    &min_lon,
+                    &min_lat,
+                    &max_lon,
+                    &max_lat,
+                    &include_hidden(&qs),
+                    &limit,
+                ],
+            )
+            .await;
+
+        match postgresresult {
+            Ok(postgresresult) => {
+                let result: Vec<StopPostgres> = postgresresult
+                    .iter()
+                    .map(|row| stop_from_row(row, None))
+                    .collect();
+
+                let json_string = to_string(&json!(result)).unwrap();
+
+                HttpResponse::Ok()
+                    .insert_header(("Content-Type", "application/json"))
+                    .body(json_string)
+            }
+            Err(e) => {
+                println!("{:?}", e);
+                println!("No results from postgres");
+
+                HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error")
+            }
+        }
+    } else {
+        HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain"))
+            .body("Couldn't connect to pool")
+    }
+}
+
+#[actix_web::get("/neareststops")]
+pub async fn neareststops(
+    pool: web::Data<bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let mut client = pool.get().await;
+
+    if client.is_ok() {
+        let mut client = client.unwrap();
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        let (lat, lon) = match (parse_f64_param(&qs, "lat"), parse_f64_param(&qs, "lon")) {
+            (Some(lat), Some(lon)) => (lat, lon),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("lat and lon are required")
+            }
+        };
+
+        let limit: i64 = qs
+            .get("limit")
+            .and_then(|limit| limit.parse::<i64>().ok())
+            .unwrap_or(10)
+            .clamp(1, 100);
+
+        //the <-> operator lets postgres walk the gist index in distance order
+        let postgresresult = client
+            .query(
+                format!(
+                    "SELECT {STOP_COLUMNS},
+                ST_Distance(point::geography, ST_SetSRID(ST_MakePoint($1, $2), 4326)::geography)
+                FROM gtfs.stops
+                WHERE ($3 OR hidden = false)
+                ORDER BY point <-> ST_SetSRID(ST_MakePoint($1, $2), 4326)
+                LIMIT $4;"
+                )
+                .as_str(),
+                &[&lon, &lat, &include_hidden(&qs), &limit],
+            )
+            .await;
+
+        match postgresresult {
+            Ok(postgresresult) => {
+                let result: Vec<StopPostgres> = postgresresult
+                    .iter()
+                    .map(|row| stop_from_row(row, row.get(15)))
+                    .collect();
+
+                let json_string = to_string(&json!(result)).unwrap();
+
+                HttpResponse::Ok()
+                    .insert_header(("Content-Type", "application/json"))
+                    .body(json_string)
+            }
+            Err(e) => {
+                println!("{:?}", e);
+                println!("No results from postgres");
+
+                HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error")
+            }
+        }
+    } else {
+        HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain"))
+            .body("Couldn't connect to pool")
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SearchStopPostgres {
+    onestop_feed_id: String,
+    gtfs_id: String,
+    name: String,
+    displayname: String,
+    lat: f64,
+    lon: f64,
+    location_type: Option<i16>,
+    route_types: Option<Vec<i16>>,
+    score: f32,
+    distance_m: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct SearchRoutePostgres {
+    onestop_feed_id: String,
+    route_id: String,
+    short_name: String,
+    long_name: String,
+    route_type: i16,
+    color: Option<String>,
+    text_color: Option<String>,
+    score: f32,
+    distance_m: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct SearchOperatorPostgres {
+    onestop_operator_id: String,
+    name: Option<String>,
+    gtfs_static_feeds: Option<Vec<String>>,
+    score: f32,
+}
+
+//text similarity comes from pg_trgm, the indexes are made in make_prod_index
+//when a point is supplied, results 50km away count half as much as results right next to it
+#[actix_web::get("/search")]
+pub async fn search(
+    pool: web::Data<bb8::Pool<bb8_postgres::PostgresConnectionManager<NoTls>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let mut client = pool.get().await;
+
+    if client.is_ok() {
+        let mut client = client.unwrap();
+        let query_str = req.query_string();
+        let qs = QString::from(query_str);
+
+        let search_text = match qs.get("q").map(|q| q.trim()) {
+            Some(q) if q.len() > 0 => q.to_string(),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("q is required")
+            }
+        };
+
+        let lat = parse_f64_param(&qs, "lat");
+        let lon = parse_f64_param(&qs, "lon");
+
+        let limit: i64 = qs
+            .get("limit")
+            .and_then(|limit| limit.parse::<i64>().ok())
+            .unwrap_or(10)
+            .clamp(1, 50);
+
+        let stops = client.query(
+            "SELECT onestop_feed_id, gtfs_id, name, displayname, lat, lon, location_type, route_types, score, distance
+            FROM (
+                SELECT onestop_feed_id, gtfs_id, name, displayname, ST_Y(point) AS lat, ST_X(point) AS lon,
+                location_type, route_types,
+                GREATEST(word_similarity($1, name), word_similarity($1, displayname)) AS score,
+                CASE WHEN $2::double precision IS NULL OR $3::double precision IS NULL THEN NULL
+                ELSE ST_Distance(point::geography, ST_SetSRID(ST_MakePoint($3, $2), 4326)::geography) END AS distance
+                FROM gtfs.stops AS stops
+                WHERE hidden = false AND (
+                    $1 <% name
+                    OR $1 <% displayname
+                    OR to_tsvector('simple', name) @@ plainto_tsquery('simple', $1)
+                    OR EXISTS (
+                        SELECT 1 FROM gtfs.stops AS alias_stops
+                        WHERE alias_stops.onestop_feed_id = stops.onestop_feed_id
+                        AND alias_stops.gtfs_id = ANY(stops.location_alias)
+                        AND $1 <% alias_stops.name
+                    )
+                )
+            ) AS matches
+            ORDER BY score / (1 + COALESCE(distance, 0) / 50000) DESC
+            LIMIT $4;",
+            &[&search_text, &lat, &lon, &limit],
+        );
+
+        let routes = client.query(
+            "SELECT onestop_feed_id, route_id, short_name, long_name, route_type, color, text_color, score, distance
+            FROM (
+                SELECT routes.onestop_feed_id, routes.route_id, routes.short_name, routes.long_name,
+                routes.route_type, routes.color, routes.text_color,
+                GREATEST(word_similarity($1, routes.short_name), word_similarity($1, routes.long_name)) AS score,
+                CASE WHEN $2::double precision IS NULL OR $3::double precision IS NULL THEN NULL
+                ELSE ST_Distance(static_feeds.hull::geography, ST_SetSRID(ST_MakePoint($3, $2), 4326)::geography) END AS distance
+                FROM gtfs.routes AS routes
+                LEFT JOIN gtfs.static_feeds AS static_feeds ON static_feeds.onestop_feed_id = routes.onestop_feed_id
+                WHERE $1 <% routes.short_name OR $1 <% routes.long_name
+            ) AS matches
+            ORDER BY score / (1 + COALESCE(distance, 0) / 50000) DESC
+            LIMIT $4;",
+            &[&search_text, &lat, &lon, &limit],
+        );
+
+        let operators = client.query(
+            "SELECT onestop_operator_id, name, gtfs_static_feeds, word_similarity($1, name) AS score
+            FROM gtfs.operators
+            WHERE $1 <% name
+            ORDER BY score DESC
+            LIMIT $2;",
+            &[&search_text, &limit],
+        );
+
+        let runqueries = join!(stops, routes, operators).await;
+
+        match runqueries {
+            (Ok(stops), Ok(routes), Ok(operators)) => {
+                let stops_result: Vec<SearchStopPostgres> = stops
+                    .iter()
+                    .map(|row| SearchStopPostgres {
+                        onestop_feed_id: row.get(0),
+                        gtfs_id: row.get(1),
+                        name: row.get(2),
+                        displayname: row.get(3),
+                        lat: row.get(4),
+                        lon: row.get(5),
+                        location_type: row.get(6),
+                        route_types: row.get(7),
+                        score: row.get(8),
+                        distance_m: row.get(9),
+                    })
+                    .collect();
+
+                let routes_result: Vec<SearchRoutePostgres> = routes
+                    .iter()
+                    .map(|row| SearchRoutePostgres {
+                        onestop_feed_id: row.get(0),
+                        route_id: row.get(1),
+                        short_name: row.get(2),
+                        long_name: row.get(3),
+                        route_type: row.get(4),
+                        color: row.get(5),
+                        text_color: row.get(6),
+                        score: row.get(7),
+                        distance_m: row.get(8),
+                    })
+                    .collect();
+
+                let operators_result: Vec<SearchOperatorPostgres> = operators
+                    .iter()
+                    .map(|row| SearchOperatorPostgres {
+                        onestop_operator_id: row.get(0),
+                        name: row.get(1),
+                        gtfs_static_feeds: row.get(2),
+                        score: row.get(3),
+                    })
+                    .collect();
+
+                let json_string = to_string(&json!({
+                    "stops": stops_result,
+                    "routes": routes_result,
+                    "operators": operators_result
+                }))
+                .unwrap();
+
+                HttpResponse::Ok()
+                    .insert_header(("Content-Type", "application/json"))
+                    .body(json_string)
+            }
+            (stops, routes, operators) => {
+                println!("{:?} {:?} {:?}", stops.err(), routes.err(), operators.err());
+                println!("No results from postgres");
+
+                HttpResponse::InternalServerError()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .body("Postgres Error")
+            }
+        }
+    } else {
+        HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain"))
+            .body("Couldn't connect to pool")
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let postgresstring = arguments::parse(std::env::args())
@@ -445,7 +2051,14 @@ async fn main() -> std::io::Result<()> {
             .service(amtrakproxy)
             .service(getinitdata)
             .service(gtfsingesterrors)
+            .service(getagency)
+            .service(stationnavigation)
+            .service(fares)
             .service(microtime)
+            .service(departures)
+            .service(stopsinbbox)
+            .service(neareststops)
+            .service(search)
     })
     .workers(16);
 
@@ -1,7 +1,6 @@
+use crate::colour_overrides::{parse_hex_colour, ColourOverrides};
 use rgb::RGB;
 
-pub const WHITE_RGB: RGB<u8> = RGB::new(255, 255, 255);
-
 pub fn fix_background_colour(input: &str) -> &str {
     if input == "ffffff" || input == "000000" {
         "0ea5e9"
@@ -18,93 +17,155 @@ pub fn fix_background_colour_rgb(background: RGB<u8>) -> RGB<u8> {
     }
 }
 
-pub fn fix_background_colour_rgb_feed(feed_id: &String, background: RGB<u8>) -> RGB<u8> {
-    match feed_id.as_str() {
-        "f-9q5b-longbeachtransit" => {
-            if (background == WHITE_RGB) {
-                return RGB::new(128, 31, 58);
-            } else {
-                return fix_background_colour_rgb(background);
-            }
-        }
-        "f-9-amtrak~amtrakcalifornia~amtrakcharteredvehicle" => RGB::new(23, 114, 172),
-        "f-9q5-metro~losangeles" => {
-            if (background == WHITE_RGB) {
-                return RGB::new(225, 103, 16);
-            } else {
-                return fix_background_colour_rgb(background);
-            }
-        }
-        _ => fix_background_colour_rgb(background),
-    }
-}
-
+//overrides from colour_overrides.json come first, then the generic fix for white and black routes
 pub fn fix_background_colour_rgb_feed_route(
+    overrides: &ColourOverrides,
     feed_id: &String,
     background: RGB<u8>,
     route: &gtfs_structures::Route,
 ) -> RGB<u8> {
-    match feed_id.as_str() {
-        "f-9q5b-longbeachtransit" => {
-            if background == WHITE_RGB {
-                return RGB::new(128, 31, 58);
-            } else {
-                return fix_background_colour_rgb(background);
-            }
-        }
-        "f-9q5-metro~losangeles" => {
-            if background == WHITE_RGB {
-                return RGB::new(225, 103, 16);
-            } else {
-                let metroid = &route.id.split("-").collect::<Vec<&str>>()[0];
-
-                if metroid.len() == 3 && metroid.chars().nth(0).unwrap() == '7' {
-                    return RGB::new(209, 18, 66);
-                }
-                return fix_background_colour_rgb(background);
-            }
-        }
-        "f-9-amtrak~amtrakcalifornia~amtrakcharteredvehicle" => RGB::new(23, 114, 172),
-        "f-9mu-mts" => match route.id.as_str() {
-            "280" => RGB::new(7, 103, 56),
-            "290" => RGB::new(235, 194, 22),
-            "237" => RGB::new(96, 41, 133),
-            "201" => RGB::new(232, 93, 152),
-            "202" => RGB::new(232, 93, 152),
-            "204" => RGB::new(232, 93, 152),
-            "235" => RGB::new(238, 45, 36),
-            "215" => RGB::new(59, 192, 225),
-            "225" => RGB::new(41, 52, 144),
-            "227" => RGB::new(123, 194, 77),
-            _ => fix_background_colour_rgb(background),
-        },
-        _ => fix_background_colour_rgb(background),
+    match overrides.background(
+        feed_id,
+        &route.id,
+        crate::route_type_to_int(&route.route_type),
+        background,
+    ) {
+        Some(colour) => colour,
+        None => fix_background_colour_rgb(background),
     }
 }
 
-pub fn fix_foreground_colour<'a>(background: &'a str, foreground: &'a str) -> &'a str {
-    if background == foreground {
-        "000000"
-    } else {
-        foreground
-    }
+//wcag AA asks for 4.5:1 between text and its background
+const MIN_TEXT_CONTRAST: f64 = 4.5;
+//and 3:1 for graphics like a line on a map
+const MIN_LINE_CONTRAST: f64 = 3.0;
+
+//roughly the land colour of the dark basemap
+pub const DARK_MAP_BACKGROUND: RGB<u8> = RGB::new(29, 29, 29);
+
+const BLACK_RGB: RGB<u8> = RGB::new(0, 0, 0);
+const WHITE_RGB: RGB<u8> = RGB::new(255, 255, 255);
+
+//relative luminance from wcag 2, 0 for black and 1 for white
+pub fn relative_luminance(colour: RGB<u8>) -> f64 {
+    let linear = |channel: u8| {
+        let channel = channel as f64 / 255.;
+        if channel <= 0.03928 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * linear(colour.r) + 0.7152 * linear(colour.g) + 0.0722 * linear(colour.b)
+}
+
+pub fn contrast_ratio(a: RGB<u8>, b: RGB<u8>) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+pub fn hex_colour(colour: RGB<u8>) -> String {
+    format!("{:02x}{:02x}{:02x}", colour.r, colour.g, colour.b)
 }
 
+//the feed's text colour when it is readable, otherwise whichever of black or white is more readable
 pub fn fix_foreground_colour_rgb(background: RGB<u8>, foreground: RGB<u8>) -> RGB<u8> {
-    if background == foreground {
-        RGB::new(0, 0, 0)
+    if contrast_ratio(background, foreground) >= MIN_TEXT_CONTRAST {
+        foreground
+    } else if contrast_ratio(background, BLACK_RGB) >= contrast_ratio(background, WHITE_RGB) {
+        BLACK_RGB
     } else {
-        foreground.clone()
+        WHITE_RGB
+    }
+}
+
+pub fn fix_foreground_colour(background: &str, foreground: &str) -> String {
+    match (parse_hex_colour(background), parse_hex_colour(foreground)) {
+        (Ok(background), Ok(foreground)) => {
+            hex_colour(fix_foreground_colour_rgb(background, foreground))
+        }
+        _ => String::from("000000"),
+    }
+}
+
+//the line colour mixed towards white until it stands out against the dark basemap, keeping its hue
+pub fn dark_map_colour_rgb(background: RGB<u8>) -> RGB<u8> {
+    let mix = |channel: u8, amount: f64| {
+        (channel as f64 + (255. - channel as f64) * amount).round() as u8
+    };
+
+    (0..=20)
+        .map(|step| step as f64 / 20.)
+        .map(|amount| {
+            RGB::new(
+                mix(background.r, amount),
+                mix(background.g, amount),
+                mix(background.b, amount),
+            )
+        })
+        .find(|colour| contrast_ratio(*colour, DARK_MAP_BACKGROUND) >= MIN_LINE_CONTRAST)
+        .unwrap_or(WHITE_RGB)
+}
+
+pub fn dark_map_colour(background: &str) -> String {
+    match parse_hex_colour(background) {
+        Ok(background) => hex_colour(dark_map_colour_rgb(background)),
+        Err(_) => hex_colour(dark_map_colour_rgb(RGB::new(14, 165, 233))),
     }
 }
 
+//background is the colour after corrections, the text colour is checked for contrast against it
 pub fn fix_foreground_colour_rgb_feed(
+    overrides: &ColourOverrides,
     feed_id: &String,
+    route: &gtfs_structures::Route,
     background: RGB<u8>,
     foreground: RGB<u8>,
 ) -> RGB<u8> {
-    match feed_id.as_str() {
-        "f-9q5b-longbeachtransit" => WHITE_RGB,
-        _ => fix_foreground_colour_rgb(background, foreground),
+    match overrides.text(
+        feed_id,
+        &route.id,
+        crate::route_type_to_int(&route.route_type),
+        route.color,
+    ) {
+        Some(colour) => colour,
+        None => fix_foreground_colour_rgb(background, foreground),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contrast_ratio_of_black_and_white() {
+        assert!((contrast_ratio(BLACK_RGB, WHITE_RGB) - 21.).abs() < 1e-9);
+        //the order of the colours doesn't matter
+        assert_eq!(
+            contrast_ratio(WHITE_RGB, BLACK_RGB),
+            contrast_ratio(BLACK_RGB, WHITE_RGB)
+        );
+        assert_eq!(contrast_ratio(WHITE_RGB, WHITE_RGB), 1.);
+    }
+
+    #[test]
+    fn text_contrast_boundary() {
+        //#767676 is the lightest grey that still reaches 4.5:1 on white, #777777 is just under
+        let passing_grey = RGB::new(0x76, 0x76, 0x76);
+        let failing_grey = RGB::new(0x77, 0x77, 0x77);
+
+        assert!(contrast_ratio(WHITE_RGB, passing_grey) >= MIN_TEXT_CONTRAST);
+        assert!(contrast_ratio(WHITE_RGB, failing_grey) < MIN_TEXT_CONTRAST);
+
+        assert_eq!(
+            fix_foreground_colour_rgb(WHITE_RGB, passing_grey),
+            passing_grey
+        );
+        assert_eq!(
+            fix_foreground_colour_rgb(WHITE_RGB, failing_grey),
+            BLACK_RGB
+        );
     }
 }
@@ -0,0 +1,222 @@
+use rgb::RGB;
+use serde::Deserialize;
+use std::fs;
+
+pub const COLOUR_OVERRIDES_PATH: &str = "colour_overrides.json";
+
+//bump when the format of the file changes in a way older importers can't read
+const SUPPORTED_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+struct RawColourOverrides {
+    version: u32,
+    overrides: Vec<RawColourOverride>,
+}
+
+//unknown keys are ignored, each override can carry a "note" saying why it exists
+#[derive(Debug, Deserialize)]
+struct RawColourOverride {
+    feed_id: Option<String>,
+    route_id: Option<String>,
+    route_id_pattern: Option<String>,
+    route_type: Option<i16>,
+    when_color: Option<String>,
+    color: Option<String>,
+    text_color: Option<String>,
+}
+
+//every condition that is set has to match, an override with no conditions applies to everything
+#[derive(Debug, Clone)]
+pub struct ColourOverride {
+    pub feed_id: Option<String>,
+    pub route_id: Option<String>,
+    //* matches any run of characters and ? matches one, eg "7??-*"
+    pub route_id_pattern: Option<String>,
+    pub route_type: Option<i16>,
+    //only replace the colour the feed gave when it is this one, eg white
+    pub when_color: Option<RGB<u8>>,
+    pub color: Option<RGB<u8>>,
+    pub text_color: Option<RGB<u8>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ColourOverrides {
+    pub overrides: Vec<ColourOverride>,
+}
+
+pub fn parse_hex_colour(input: &str) -> Result<RGB<u8>, String> {
+    let input = input.trim().trim_start_matches('#');
+
+    if input.len() != 6 || !input.is_ascii() {
+        return Err(format!("{} is not a 6 digit hex colour", input));
+    }
+
+    let channel = |range: std::ops::Range<usize>| {
+        u8::from_str_radix(&input[range], 16)
+            .map_err(|_| format!("{} is not a 6 digit hex colour", input))
+    };
+
+    Ok(RGB::new(channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+fn parse_optional_colour(input: &Option<String>) -> Result<Option<RGB<u8>>, String> {
+    match input {
+        Some(input) => parse_hex_colour(input).map(Some),
+        None => Ok(None),
+    }
+}
+
+//glob matching for route ids, without pulling in a regex engine for it
+pub fn matches_pattern(pattern: &str, input: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let input = input.chars().collect::<Vec<char>>();
+
+    let (mut p, mut i) = (0, 0);
+    //position of the last * and where in the input it started matching
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while i < input.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == input[i]) {
+            p = p + 1;
+            i = i + 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, i));
+            p = p + 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            i = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl ColourOverride {
+    //the conditions that pick routes, without when_color, which needs the colour the feed gave
+    pub fn matches_route(&self, feed_id: &str, route_id: &str, route_type: i16) -> bool {
+        self.feed_id.as_deref().map_or(true, |id| id == feed_id)
+            && self.route_id.as_deref().map_or(true, |id| id == route_id)
+            && self
+                .route_id_pattern
+                .as_deref()
+                .map_or(true, |pattern| matches_pattern(pattern, route_id))
+            && self.route_type.map_or(true, |kind| kind == route_type)
+    }
+
+    pub fn matches(
+        &self,
+        feed_id: &str,
+        route_id: &str,
+        route_type: i16,
+        background: RGB<u8>,
+    ) -> bool {
+        self.matches_route(feed_id, route_id, route_type)
+            && self.when_color.map_or(true, |colour| colour == background)
+    }
+}
+
+impl ColourOverrides {
+    //the first matching override that sets a background wins, so list specific overrides first
+    pub fn background(
+        &self,
+        feed_id: &str,
+        route_id: &str,
+        route_type: i16,
+        background: RGB<u8>,
+    ) -> Option<RGB<u8>> {
+        self.overrides
+            .iter()
+            .filter(|colour_override| {
+                colour_override.matches(feed_id, route_id, route_type, background)
+            })
+            .find_map(|colour_override| colour_override.color)
+    }
+
+    pub fn text(
+        &self,
+        feed_id: &str,
+        route_id: &str,
+        route_type: i16,
+        background: RGB<u8>,
+    ) -> Option<RGB<u8>> {
+        self.overrides
+            .iter()
+            .filter(|colour_override| {
+                colour_override.matches(feed_id, route_id, route_type, background)
+            })
+            .find_map(|colour_override| colour_override.text_color)
+    }
+}
+
+fn parse_override(raw: RawColourOverride) -> Result<ColourOverride, String> {
+    let parsed = ColourOverride {
+        when_color: parse_optional_colour(&raw.when_color)?,
+        color: parse_optional_colour(&raw.color)?,
+        text_color: parse_optional_colour(&raw.text_color)?,
+        feed_id: raw.feed_id,
+        route_id: raw.route_id,
+        route_id_pattern: raw.route_id_pattern,
+        route_type: raw.route_type,
+    };
+
+    match parsed.color.is_some() || parsed.text_color.is_some() {
+        true => Ok(parsed),
+        false => Err(String::from("sets neither color nor text_color")),
+    }
+}
+
+pub fn read_colour_overrides(path: &str) -> Result<ColourOverrides, String> {
+    let file = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let raw: RawColourOverrides =
+        serde_json::from_str(&file).map_err(|err| format!("{}: {}", path, err))?;
+
+    if raw.version != SUPPORTED_VERSION {
+        return Err(format!(
+            "{} is version {}, this importer reads version {}",
+            path, raw.version, SUPPORTED_VERSION
+        ));
+    }
+
+    let overrides = raw
+        .overrides
+        .into_iter()
+        .enumerate()
+        .map(|(index, raw)| {
+            parse_override(raw).map_err(|err| format!("{} override {}: {}", path, index, err))
+        })
+        .collect::<Result<Vec<ColourOverride>, String>>()?;
+
+    Ok(ColourOverrides {
+        overrides: overrides,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_route_ids() {
+        assert!(matches_pattern("7??-*", "701-Eastbound"));
+        assert!(matches_pattern("7??-*", "799-"));
+        //* can match nothing at all, even at the start
+        assert!(matches_pattern("*X", "X"));
+        assert!(matches_pattern("*", ""));
+        //a later * has to backtrack past an earlier partial match
+        assert!(matches_pattern("a*b*c", "aXbYbZc"));
+    }
+
+    #[test]
+    fn patterns_that_do_not_match() {
+        //? is exactly one character
+        assert!(!matches_pattern("7??-*", "70-Eastbound"));
+        assert!(!matches_pattern("7??-*", "7010-Eastbound"));
+        //without a * the whole id has to match
+        assert!(!matches_pattern("Red", "Red Line"));
+        assert!(!matches_pattern("Red", "red"));
+        assert!(!matches_pattern("", "Red"));
+    }
+}
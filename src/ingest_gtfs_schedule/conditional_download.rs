@@ -0,0 +1,218 @@
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::RequestBuilder;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+pub const STATE_PATH: &str = "gtfs_static_zips/download_state.json";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DownloadState {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub sha1: Option<String>,
+    //etag of the response a .part file came from, so an interrupted body can be resumed with a range request
+    pub partial_etag: Option<String>,
+}
+
+pub enum DownloadOutcome {
+    Changed,
+    Unchanged,
+    Failed(String),
+}
+
+pub fn read_state() -> HashMap<String, DownloadState> {
+    match fs::read_to_string(STATE_PATH) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+pub fn write_state(state: &HashMap<String, DownloadState>) -> std::io::Result<()> {
+    fs::write(STATE_PATH, serde_json::to_string_pretty(state)?)
+}
+
+fn header_string(
+    response: &reqwest::Response,
+    name: reqwest::header::HeaderName,
+) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+fn sha1_of_file(path: &str) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn is_valid_zip(path: &str) -> bool {
+    match File::open(path).map(zip::ZipArchive::new) {
+        Ok(Ok(archive)) => archive.len() > 0,
+        _ => false,
+    }
+}
+
+//asks for the zip only if it changed since the copy on disk was downloaded
+fn conditional_request(
+    request: RequestBuilder,
+    previous: &DownloadState,
+    zip_path: &str,
+) -> RequestBuilder {
+    if fs::metadata(zip_path).is_err() {
+        return request;
+    }
+
+    let mut request = request;
+    if let Some(etag) = &previous.etag {
+        request = request.header(IF_NONE_MATCH, etag.as_str());
+    }
+    if let Some(last_modified) = &previous.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+    }
+    request
+}
+
+//downloads into {feed_id}.zip.part and only renames it over {feed_id}.zip once a valid zip with a new hash arrived
+pub async fn download_feed(
+    request: RequestBuilder,
+    feed_id: &str,
+    previous: &DownloadState,
+) -> (DownloadOutcome, DownloadState) {
+    let zip_path = format!("gtfs_static_zips/{}.zip", feed_id);
+    let part_path = format!("gtfs_static_zips/{}.zip.part", feed_id);
+
+    let mut state = previous.clone();
+
+    let partial_length = fs::metadata(&part_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    //kept without the range headers in case the server refuses the range
+    let full_request = request.try_clone();
+
+    let (request, ranged) = match (&previous.partial_etag, partial_length) {
+        (Some(partial_etag), partial_length) if partial_length > 0 => (
+            request
+                .header(RANGE, format!("bytes={}-", partial_length))
+                .header(IF_RANGE, partial_etag.as_str()),
+            true,
+        ),
+        _ => (conditional_request(request, previous, &zip_path), false),
+    };
+
+    let mut response = match request.send().await {
+        Ok(response) => response,
+        Err(err) => return (DownloadOutcome::Failed(format!("{}", err)), state),
+    };
+
+    //416 when the file got shorter than the .part, or any other refusal, starts over from the beginning
+    if ranged && !response.status().is_success() {
+        let _ = fs::remove_file(&part_path);
+        state.partial_etag = None;
+
+        let full_request = match full_request {
+            Some(full_request) => conditional_request(full_request, previous, &zip_path),
+            None => {
+                return (
+                    DownloadOutcome::Failed(format!(
+                        "status {} for the range request",
+                        response.status()
+                    )),
+                    state,
+                )
+            }
+        };
+
+        response = match full_request.send().await {
+            Ok(response) => response,
+            Err(err) => return (DownloadOutcome::Failed(format!("{}", err)), state),
+        };
+    }
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return (DownloadOutcome::Unchanged, state);
+    }
+
+    if !response.status().is_success() {
+        return (
+            DownloadOutcome::Failed(format!("status {}", response.status())),
+            state,
+        );
+    }
+
+    //206 means the server honoured the range, anything else is the whole file again
+    let file = match response.status() == StatusCode::PARTIAL_CONTENT {
+        true => OpenOptions::new().append(true).open(&part_path),
+        false => File::create(&part_path),
+    };
+
+    let mut file = match file {
+        Ok(file) => file,
+        Err(err) => return (DownloadOutcome::Failed(format!("{}", err)), state),
+    };
+
+    let etag = header_string(&response, ETAG);
+    let last_modified = header_string(&response, LAST_MODIFIED);
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if let Err(err) = file.write_all(&chunk) {
+                    return (DownloadOutcome::Failed(format!("{}", err)), state);
+                }
+            }
+            Ok(None) => break,
+            Err(err) => {
+                //keep the .part around, the next run continues from here if the server has an etag
+                state.partial_etag = etag;
+                return (
+                    DownloadOutcome::Failed(format!("body interrupted: {}", err)),
+                    state,
+                );
+            }
+        }
+    }
+
+    drop(file);
+    state.partial_etag = None;
+
+    if !is_valid_zip(&part_path) {
+        let _ = fs::remove_file(&part_path);
+        return (
+            DownloadOutcome::Failed(String::from("not a valid zip file")),
+            state,
+        );
+    }
+
+    let sha1 = match sha1_of_file(&part_path) {
+        Ok(sha1) => sha1,
+        Err(err) => return (DownloadOutcome::Failed(format!("{}", err)), state),
+    };
+
+    state.etag = etag;
+    state.last_modified = last_modified;
+
+    //plenty of agencies don't send etags, the hash still catches a re-upload of the same file
+    if previous.sha1.as_ref() == Some(&sha1) && fs::metadata(&zip_path).is_ok() {
+        let _ = fs::remove_file(&part_path);
+        return (DownloadOutcome::Unchanged, state);
+    }
+
+    if let Err(err) = fs::rename(&part_path, &zip_path) {
+        return (DownloadOutcome::Failed(format!("{}", err)), state);
+    }
+
+    state.sha1 = Some(sha1);
+
+    (DownloadOutcome::Changed, state)
+}
@@ -0,0 +1,28 @@
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::Type;
+use tokio_postgres::Transaction;
+
+//starts a binary COPY FROM STDIN into schemaname.table
+//column types are read off an empty select, so postgis geometry columns get the right oid for this database
+pub async fn start_binary_copy(
+    client: &Transaction<'_>,
+    schemaname: &str,
+    table: &str,
+    columns: &[&str],
+) -> Result<BinaryCopyInWriter, tokio_postgres::Error> {
+    let column_list = columns.join(", ");
+
+    let types = client
+        .prepare(format!("SELECT {column_list} FROM {schemaname}.{table} LIMIT 0;").as_str())
+        .await?
+        .columns()
+        .iter()
+        .map(|column| column.type_().clone())
+        .collect::<Vec<Type>>();
+
+    let sink = client
+        .copy_in(format!("COPY {schemaname}.{table} ({column_list}) FROM STDIN BINARY;").as_str())
+        .await?;
+
+    Ok(BinaryCopyInWriter::new(sink, &types))
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+
+//any of these marks the folder inside the zip that actually holds the feed
+const GTFS_MARKER_FILES: [&str; 3] = ["agency.txt", "stops.txt", "routes.txt"];
+
+pub struct ExtractedFeed {
+    pub files_written: usize,
+    //folder inside the zip the files were taken from, empty when they were at the top
+    pub gtfs_root: PathBuf,
+}
+
+fn is_junk_path(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => {
+            let name = name.to_string_lossy();
+            name == "__MACOSX" || name.starts_with("._") || name == ".DS_Store"
+        }
+        _ => false,
+    })
+}
+
+//unpacks a feed zip into destination_folder with every GTFS file at the top level
+//the zip is extracted into a sibling .extracting folder first so a broken zip never leaves half a feed behind
+pub fn extract_feed(zip_path: &Path, destination_folder: &Path) -> Result<ExtractedFeed, String> {
+    let file = File::open(zip_path).map_err(|err| format!("could not open zip: {}", err))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|err| format!("not a valid zip: {}", err))?;
+
+    let mut entries: Vec<(usize, PathBuf)> = vec![];
+
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .map_err(|err| format!("could not read entry {}: {}", index, err))?;
+
+        //enclosed_name is None for absolute paths and anything escaping the folder with ..
+        let path = match entry.enclosed_name() {
+            Some(path) => path.to_path_buf(),
+            None => return Err(format!("unsafe path in zip: {}", entry.name())),
+        };
+
+        if entry.is_dir() || is_junk_path(&path) {
+            continue;
+        }
+
+        entries.push((index, path));
+    }
+
+    //some agencies zip the folder instead of the files, sometimes several levels deep
+    let gtfs_root = entries
+        .iter()
+        .filter(|(_, path)| {
+            path.file_name()
+                .map(|name| {
+                    GTFS_MARKER_FILES.contains(&name.to_string_lossy().to_lowercase().as_str())
+                })
+                .unwrap_or(false)
+        })
+        .filter_map(|(_, path)| path.parent().map(|parent| parent.to_path_buf()))
+        .min_by_key(|parent| parent.components().count())
+        .ok_or_else(|| String::from("no GTFS files found in zip"))?;
+
+    let mut staging_folder = destination_folder.as_os_str().to_owned();
+    staging_folder.push(".extracting");
+    let staging_folder = PathBuf::from(staging_folder);
+
+    if staging_folder.exists() {
+        fs::remove_dir_all(&staging_folder)
+            .map_err(|err| format!("could not clear {}: {}", staging_folder.display(), err))?;
+    }
+
+    fs::create_dir_all(&staging_folder)
+        .map_err(|err| format!("could not create {}: {}", staging_folder.display(), err))?;
+
+    let mut files_written = 0;
+
+    for (index, path) in entries.iter() {
+        if path.parent() != Some(gtfs_root.as_path()) {
+            continue;
+        }
+
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+
+        let mut entry = archive
+            .by_index(*index)
+            .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+        let mut out = File::create(staging_folder.join(file_name))
+            .map_err(|err| format!("could not create {}: {}", path.display(), err))?;
+
+        std::io::copy(&mut entry, &mut out)
+            .map_err(|err| format!("could not extract {}: {}", path.display(), err))?;
+
+        files_written = files_written + 1;
+    }
+
+    if destination_folder.exists() {
+        fs::remove_dir_all(destination_folder)
+            .map_err(|err| format!("could not clear {}: {}", destination_folder.display(), err))?;
+    }
+
+    fs::rename(&staging_folder, destination_folder)
+        .map_err(|err| format!("could not move feed into place: {}", err))?;
+
+    Ok(ExtractedFeed {
+        files_written,
+        gtfs_root,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    //writes a zip with the given (name, contents) entries into a fresh folder under the temp dir
+    fn make_zip(test_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!(
+            "catenary-extract-feed-{}-{}",
+            test_name,
+            std::process::id()
+        ));
+
+        if folder.exists() {
+            fs::remove_dir_all(&folder).unwrap();
+        }
+        fs::create_dir_all(&folder).unwrap();
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+
+        for (name, contents) in files {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+
+        let zip_path = folder.join("feed.zip");
+        fs::write(&zip_path, writer.finish().unwrap().into_inner()).unwrap();
+
+        zip_path
+    }
+
+    #[test]
+    fn rejects_entries_escaping_the_folder() {
+        let zip_path = make_zip(
+            "zip-slip",
+            &[("stops.txt", "stop_id\n"), ("../evil.txt", "gotcha")],
+        );
+        let folder = zip_path.parent().unwrap();
+        let destination = folder.join("feed");
+
+        let result = extract_feed(&zip_path, &destination);
+
+        assert!(matches!(result, Err(err) if err.starts_with("unsafe path in zip")));
+        assert!(!destination.exists());
+        assert!(!folder.parent().unwrap().join("evil.txt").exists());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn finds_a_feed_zipped_inside_a_folder() {
+        let zip_path = make_zip(
+            "nested-root",
+            &[
+                ("feed/stops.txt", "stop_id\n"),
+                ("feed/routes.txt", "route_id\n"),
+                ("__MACOSX/feed/._stops.txt", ""),
+                ("readme.txt", "not part of the feed"),
+            ],
+        );
+        let folder = zip_path.parent().unwrap();
+        let destination = folder.join("feed");
+
+        let extracted = extract_feed(&zip_path, &destination).unwrap();
+
+        assert_eq!(extracted.gtfs_root, PathBuf::from("feed"));
+        assert_eq!(extracted.files_written, 2);
+        assert!(destination.join("stops.txt").exists());
+        assert!(destination.join("routes.txt").exists());
+        //only the feed folder is kept, files next to it are left out
+        assert!(!destination.join("readme.txt").exists());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,355 @@
+use crate::gtfs_csv::read_optional_gtfs_file;
+use crate::ingest_errors::{IngestError, IngestStage};
+use serde::Deserialize;
+use tokio_postgres::Transaction;
+
+//fares v1
+
+#[derive(Debug, Deserialize)]
+pub struct RawFareAttribute {
+    pub fare_id: String,
+    pub price: f64,
+    pub currency_type: String,
+    pub payment_method: i16,
+    //empty means unlimited transfers
+    pub transfers: Option<i16>,
+    #[serde(default)]
+    pub agency_id: Option<String>,
+    #[serde(default)]
+    pub transfer_duration: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawFareRule {
+    pub fare_id: String,
+    #[serde(default)]
+    pub route_id: Option<String>,
+    #[serde(default)]
+    pub origin_id: Option<String>,
+    #[serde(default)]
+    pub destination_id: Option<String>,
+    #[serde(default)]
+    pub contains_id: Option<String>,
+}
+
+//fares v2
+
+#[derive(Debug, Deserialize)]
+pub struct RawFareProduct {
+    pub fare_product_id: String,
+    #[serde(default)]
+    pub fare_product_name: Option<String>,
+    #[serde(default)]
+    pub fare_media_id: Option<String>,
+    pub amount: f64,
+    pub currency: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawFareLegRule {
+    #[serde(default)]
+    pub leg_group_id: Option<String>,
+    #[serde(default)]
+    pub network_id: Option<String>,
+    #[serde(default)]
+    pub from_area_id: Option<String>,
+    #[serde(default)]
+    pub to_area_id: Option<String>,
+    #[serde(default)]
+    pub from_timeframe_group_id: Option<String>,
+    #[serde(default)]
+    pub to_timeframe_group_id: Option<String>,
+    pub fare_product_id: String,
+    #[serde(default)]
+    pub rule_priority: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawFareTransferRule {
+    #[serde(default)]
+    pub from_leg_group_id: Option<String>,
+    #[serde(default)]
+    pub to_leg_group_id: Option<String>,
+    #[serde(default)]
+    pub transfer_count: Option<i32>,
+    #[serde(default)]
+    pub duration_limit: Option<i32>,
+    #[serde(default)]
+    pub duration_limit_type: Option<i16>,
+    pub fare_transfer_type: i16,
+    #[serde(default)]
+    pub fare_product_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawArea {
+    pub area_id: String,
+    #[serde(default)]
+    pub area_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawStopArea {
+    pub area_id: String,
+    pub stop_id: String,
+}
+
+//networks come either from route_networks.txt or a network_id column in routes.txt
+#[derive(Debug, Deserialize)]
+pub struct RawRouteNetwork {
+    pub route_id: String,
+    #[serde(default)]
+    pub network_id: Option<String>,
+}
+
+fn read_file<T: serde::de::DeserializeOwned>(
+    folder: &str,
+    file_name: &str,
+) -> Result<Vec<T>, IngestError> {
+    read_optional_gtfs_file::<T>(folder, file_name).stage("fares")
+}
+
+//reads both fare models from the feed folder and inserts them, old rows were already deleted in this transaction
+pub async fn insert_fares(
+    client: &Transaction<'_>,
+    schemaname: &str,
+    feed_id: &str,
+    folder: &str,
+) -> Result<(), IngestError> {
+    let fare_attributes = read_file::<RawFareAttribute>(folder, "fare_attributes.txt")?;
+    let fare_rules = read_file::<RawFareRule>(folder, "fare_rules.txt")?;
+    let fare_products = read_file::<RawFareProduct>(folder, "fare_products.txt")?;
+    let fare_leg_rules = read_file::<RawFareLegRule>(folder, "fare_leg_rules.txt")?;
+    let fare_transfer_rules = read_file::<RawFareTransferRule>(folder, "fare_transfer_rules.txt")?;
+    let areas = read_file::<RawArea>(folder, "areas.txt")?;
+    let stop_areas = read_file::<RawStopArea>(folder, "stop_areas.txt")?;
+
+    let mut route_networks = read_file::<RawRouteNetwork>(folder, "route_networks.txt")?;
+    if route_networks.is_empty() && !fare_leg_rules.is_empty() {
+        route_networks = read_file::<RawRouteNetwork>(folder, "routes.txt")?
+            .into_iter()
+            .filter(|route| route.network_id.is_some())
+            .collect();
+    }
+
+    println!(
+        "{} fare attributes, {} fare rules, {} fare products, {} fare leg rules",
+        fare_attributes.len(),
+        fare_rules.len(),
+        fare_products.len(),
+        fare_leg_rules.len()
+    );
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.fare_attributes
+        (onestop_feed_id, fare_id, price, currency_type, payment_method, transfers, agency_id, transfer_duration)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING;"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for fare_attribute in &fare_attributes {
+        client
+            .query(
+                &statement,
+                &[
+                    &feed_id,
+                    &fare_attribute.fare_id,
+                    &fare_attribute.price,
+                    &fare_attribute.currency_type,
+                    &fare_attribute.payment_method,
+                    &fare_attribute.transfers,
+                    &fare_attribute.agency_id,
+                    &fare_attribute.transfer_duration,
+                ],
+            )
+            .await
+            .stage("fares")?;
+    }
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.fare_rules
+        (onestop_feed_id, fare_id, route_id, origin_id, destination_id, contains_id)
+        VALUES ($1, $2, $3, $4, $5, $6);"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for fare_rule in &fare_rules {
+        client
+            .query(
+                &statement,
+                &[
+                    &feed_id,
+                    &fare_rule.fare_id,
+                    &fare_rule.route_id,
+                    &fare_rule.origin_id,
+                    &fare_rule.destination_id,
+                    &fare_rule.contains_id,
+                ],
+            )
+            .await
+            .stage("fares")?;
+    }
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.fare_products
+        (onestop_feed_id, fare_product_id, fare_product_name, fare_media_id, amount, currency)
+        VALUES ($1, $2, $3, $4, $5, $6);"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for fare_product in &fare_products {
+        client
+            .query(
+                &statement,
+                &[
+                    &feed_id,
+                    &fare_product.fare_product_id,
+                    &fare_product.fare_product_name,
+                    &fare_product.fare_media_id,
+                    &fare_product.amount,
+                    &fare_product.currency,
+                ],
+            )
+            .await
+            .stage("fares")?;
+    }
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.fare_leg_rules
+        (onestop_feed_id, leg_group_id, network_id, from_area_id, to_area_id, from_timeframe_group_id, to_timeframe_group_id, fare_product_id, rule_priority)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for fare_leg_rule in &fare_leg_rules {
+        client
+            .query(
+                &statement,
+                &[
+                    &feed_id,
+                    &fare_leg_rule.leg_group_id,
+                    &fare_leg_rule.network_id,
+                    &fare_leg_rule.from_area_id,
+                    &fare_leg_rule.to_area_id,
+                    &fare_leg_rule.from_timeframe_group_id,
+                    &fare_leg_rule.to_timeframe_group_id,
+                    &fare_leg_rule.fare_product_id,
+                    &fare_leg_rule.rule_priority,
+                ],
+            )
+            .await
+            .stage("fares")?;
+    }
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.fare_transfer_rules
+        (onestop_feed_id, from_leg_group_id, to_leg_group_id, transfer_count, duration_limit, duration_limit_type, fare_transfer_type, fare_product_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8);"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for fare_transfer_rule in &fare_transfer_rules {
+        client
+            .query(
+                &statement,
+                &[
+                    &feed_id,
+                    &fare_transfer_rule.from_leg_group_id,
+                    &fare_transfer_rule.to_leg_group_id,
+                    &fare_transfer_rule.transfer_count,
+                    &fare_transfer_rule.duration_limit,
+                    &fare_transfer_rule.duration_limit_type,
+                    &fare_transfer_rule.fare_transfer_type,
+                    &fare_transfer_rule.fare_product_id,
+                ],
+            )
+            .await
+            .stage("fares")?;
+    }
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.areas (onestop_feed_id, area_id, area_name)
+        VALUES ($1, $2, $3) ON CONFLICT DO NOTHING;"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for area in &areas {
+        client
+            .query(&statement, &[&feed_id, &area.area_id, &area.area_name])
+            .await
+            .stage("fares")?;
+    }
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.stop_areas (onestop_feed_id, area_id, stop_id)
+        VALUES ($1, $2, $3) ON CONFLICT DO NOTHING;"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for stop_area in &stop_areas {
+        client
+            .query(
+                &statement,
+                &[&feed_id, &stop_area.area_id, &stop_area.stop_id],
+            )
+            .await
+            .stage("fares")?;
+    }
+
+    let statement = client
+        .prepare(
+            format!(
+                "INSERT INTO {schemaname}.route_networks (onestop_feed_id, route_id, network_id)
+        VALUES ($1, $2, $3) ON CONFLICT DO NOTHING;"
+            )
+            .as_str(),
+        )
+        .await
+        .stage("fares")?;
+
+    for route_network in &route_networks {
+        if let Some(network_id) = &route_network.network_id {
+            client
+                .query(&statement, &[&feed_id, &route_network.route_id, network_id])
+                .await
+                .stage("fares")?;
+        }
+    }
+
+    Ok(())
+}
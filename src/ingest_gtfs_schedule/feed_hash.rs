@@ -0,0 +1,28 @@
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::fs::File;
+
+//sha1 over every .txt file in the feed folder, in name order, so a re-zipped but identical feed still matches
+pub fn hash_feed_folder(folder: &str) -> std::io::Result<String> {
+    let mut file_paths = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension().map(|ext| ext == "txt").unwrap_or(false))
+        .collect::<Vec<_>>();
+
+    file_paths.sort();
+
+    let mut hasher = Sha1::new();
+
+    for file_path in file_paths {
+        if let Some(file_name) = file_path.file_name() {
+            hasher.update(file_name.to_string_lossy().as_bytes());
+        }
+
+        let mut file = File::open(&file_path)?;
+        std::io::copy(&mut file, &mut hasher)?;
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+pub const MANIFEST_PATH: &str = "gtfs_static_zips/manifest.json";
+
+//written by transitlanddownload, lets import only re-ingest feeds that changed
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FeedManifest {
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+    //feed id and why it failed
+    pub failed: Vec<(String, String)>,
+}
+
+pub fn read_manifest() -> Option<FeedManifest> {
+    let contents = fs::read_to_string(MANIFEST_PATH).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+pub fn write_manifest(manifest: &FeedManifest) -> std::io::Result<()> {
+    fs::write(MANIFEST_PATH, serde_json::to_string_pretty(manifest)?)
+}
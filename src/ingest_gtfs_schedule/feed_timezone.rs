@@ -0,0 +1,51 @@
+use aspenlib::gtfs_time::parse_timezone;
+use chrono_tz::Tz;
+
+//every agency in a feed is required to share one timezone, the first valid one is the feed default
+pub fn feed_default_timezone(gtfs: &gtfs_structures::Gtfs) -> Option<Tz> {
+    gtfs.agencies
+        .iter()
+        .find_map(|agency| parse_timezone(Some(agency.timezone.as_str())))
+}
+
+//agencies with a timezone that isn't a valid iana name, or that disagree with the feed default
+pub fn invalid_agency_timezones(gtfs: &gtfs_structures::Gtfs, default: Option<&Tz>) -> Vec<String> {
+    gtfs.agencies
+        .iter()
+        .filter(|agency| parse_timezone(Some(agency.timezone.as_str())).as_ref() != default)
+        .map(|agency| {
+            format!(
+                "{} ({})",
+                agency.id.as_deref().unwrap_or(agency.name.as_str()),
+                agency.timezone
+            )
+        })
+        .collect()
+}
+
+//stop_timezone of the stop, else of its parent station, else the agency timezone of the feed
+pub fn stop_timezone(
+    gtfs: &gtfs_structures::Gtfs,
+    stop: &gtfs_structures::Stop,
+    default: Option<&Tz>,
+) -> Option<Tz> {
+    let mut current = stop;
+
+    //boarding area -> platform -> station is as deep as gtfs goes
+    for _ in 0..3 {
+        if let Some(timezone) = parse_timezone(current.timezone.as_deref()) {
+            return Some(timezone);
+        }
+
+        current = match current
+            .parent_station
+            .as_ref()
+            .and_then(|parent_station| gtfs.stops.get(parent_station))
+        {
+            Some(parent) => parent.as_ref(),
+            None => break,
+        };
+    }
+
+    default.copied()
+}
@@ -1,16 +1,58 @@
-use geo::CoordsIter;
-use geo_types::Point;
-use postgis::ewkb;
 extern crate time;
 extern crate travelling_salesman;
 use geo::ConvexHull;
-use geo::EuclideanDistance;
+use geo::GeodesicDistance;
 use geo::HaversineDistance;
 use geo::HaversineLength;
-use geo::{line_string, polygon, GeodesicDistance};
-use geo_postgis::{FromPostgis, ToPostgis};
+use geo::LineLocatePoint;
+
+//a shape is broken when it is far longer than the area it covers, points jumping back and forth across the route
+pub struct BrokenShapeThresholds {
+    //length of the line divided by the largest distance between two of its points
+    pub max_ratio: f64,
+    //in metres, short shapes are left alone
+    pub min_length: f64,
+    pub tsp_milliseconds: i64,
+}
+
+impl Default for BrokenShapeThresholds {
+    fn default() -> Self {
+        BrokenShapeThresholds {
+            max_ratio: 20.,
+            min_length: 1000.,
+            tsp_milliseconds: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShapeRepair {
+    NotNeeded,
+    //points sorted by where they fall along the stops of a trip using the shape
+    StopOrder,
+    TravellingSalesman,
+    //still over the threshold after every repair, the original shape is kept
+    Failed,
+}
+
+impl ShapeRepair {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShapeRepair::NotNeeded => "not_needed",
+            ShapeRepair::StopOrder => "stop_order",
+            ShapeRepair::TravellingSalesman => "travelling_salesman",
+            ShapeRepair::Failed => "failed",
+        }
+    }
+}
 
-use std::f64::consts::PI;
+pub struct ShapeQuality {
+    pub length: f64,
+    pub diameter: f64,
+    pub ratio: f64,
+    pub repair: ShapeRepair,
+    pub repaired_ratio: Option<f64>,
+}
 
 fn rotating_calipers_diameter(polygon: &Vec<geo::Point>) -> f64 {
     let n = polygon.len();
@@ -42,61 +84,132 @@ fn rotating_calipers_diameter(polygon: &Vec<geo::Point>) -> f64 {
     res
 }
 
-pub fn fix_broken_lines(
-    linestring: ewkb::LineStringT<ewkb::Point>,
-    feed_id: &str,
-) -> ewkb::LineStringT<ewkb::Point> {
-    let geo_typed = geo_types::LineString::from_postgis(&linestring);
+//(length, diameter, ratio) in metres
+pub fn length_to_diameter(line: &geo::LineString) -> (f64, f64, f64) {
+    let length = line.haversine_length();
 
-    if feed_id == "f-ezjm-informaciónoficial~consorcioregionaldetransportesdemadrid" {
-        let length = geo_typed.haversine_length();
+    let mut convex_hull_points = line.convex_hull().exterior().clone().into_points();
+    //the exterior is closed, drop the repeated first point
+    if convex_hull_points.len() > 1 && convex_hull_points.first() == convex_hull_points.last() {
+        convex_hull_points.pop();
+    }
 
-        println!("{}", length);
+    let diameter = match convex_hull_points.len() {
+        0 => 0.,
+        1 => 0.,
+        2 => convex_hull_points[0].geodesic_distance(&convex_hull_points[1]),
+        _ => rotating_calipers_diameter(&convex_hull_points),
+    };
 
-        let convex_hull = geo_typed.convex_hull();
+    let ratio = match diameter > 0. {
+        true => length / diameter,
+        false => 1.,
+    };
 
-        let convex_hull_points = convex_hull.exterior().clone().into_points();
+    (length, diameter, ratio)
+}
 
-        let farthest_points_distance = match convex_hull_points.len() {
-            0 => 0.,
-            1 => 0.,
-            2 => convex_hull_points[0].geodesic_distance(&convex_hull_points[1]),
-            _ => rotating_calipers_diameter(&convex_hull_points),
-        };
+fn is_broken(length: f64, ratio: f64, thresholds: &BrokenShapeThresholds) -> bool {
+    ratio > thresholds.max_ratio && length > thresholds.min_length
+}
 
-        println!(
-            "{} shape size and {} line distance",
-            farthest_points_distance, length
-        );
+//sorts the shape points by how far along the stop sequence they are, ties keep their original order
+fn order_by_stops(line: &geo::LineString, stops: &[geo::Point]) -> Option<geo::LineString> {
+    if stops.len() < 2 {
+        return None;
+    }
 
-        let ratio = length / farthest_points_distance;
-        println!("Ratio {}", ratio);
+    let stop_line = geo::LineString::from(stops.to_vec());
 
-        if ratio > 20. && length > 1000. {
-            println!("doing travelling salesman");
+    let mut positions = line
+        .points()
+        .enumerate()
+        .map(|(index, point)| Some((stop_line.line_locate_point(&point)?, index)))
+        .collect::<Option<Vec<(f64, usize)>>>()?;
 
-            let points = geo_typed
-                .points()
-                .map(|point| (point.x(), point.y()))
-                .collect::<Vec<(f64, f64)>>();
+    positions.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
 
-            let tour = travelling_salesman::simulated_annealing::solve(
-                &points,
-                time::Duration::milliseconds(5000),
-            );
+    Some(geo::LineString::new(
+        positions
+            .into_iter()
+            .map(|(_, index)| line.0[index])
+            .collect(),
+    ))
+}
 
-            let result: geo::LineString = tour
-                .route
-                .iter()
-                .map(|index| points[*index])
-                .collect::<Vec<(f64, f64)>>()
-                .into();
+//spins a cpu for tsp_milliseconds, so it is run with spawn_blocking
+fn order_by_travelling_salesman(line: &geo::LineString, tsp_milliseconds: i64) -> geo::LineString {
+    let points = line
+        .points()
+        .map(|point| (point.x(), point.y()))
+        .collect::<Vec<(f64, f64)>>();
+
+    let tour = travelling_salesman::simulated_annealing::solve(
+        &points,
+        time::Duration::milliseconds(tsp_milliseconds),
+    );
+
+    tour.route
+        .iter()
+        .map(|index| points[*index])
+        .collect::<Vec<(f64, f64)>>()
+        .into()
+}
 
-            return result.to_postgis_wgs84();
+//stops are the stop coordinates of a trip using the shape, in stop_sequence order, and may be empty
+pub async fn fix_broken_lines(
+    line: geo::LineString,
+    stops: &[geo::Point],
+    thresholds: &BrokenShapeThresholds,
+) -> (geo::LineString, ShapeQuality) {
+    let (length, diameter, ratio) = length_to_diameter(&line);
+
+    let mut quality = ShapeQuality {
+        length: length,
+        diameter: diameter,
+        ratio: ratio,
+        repair: ShapeRepair::NotNeeded,
+        repaired_ratio: None,
+    };
+
+    if !is_broken(length, ratio, thresholds) {
+        return (line, quality);
+    }
+
+    //the stop order gives the same answer every run, the travelling salesman doesn't
+    if let Some(repaired) = order_by_stops(&line, stops) {
+        let (repaired_length, _, repaired_ratio) = length_to_diameter(&repaired);
+        if !is_broken(repaired_length, repaired_ratio, thresholds) {
+            quality.repair = ShapeRepair::StopOrder;
+            quality.repaired_ratio = Some(repaired_ratio);
+            return (repaired, quality);
         }
     }
 
-    linestring
+    //keeps the anneal off the tokio workers the other feeds are importing on
+    let line_to_order = line.clone();
+    let tsp_milliseconds = thresholds.tsp_milliseconds;
+    let repaired = match tokio::task::spawn_blocking(move || {
+        order_by_travelling_salesman(&line_to_order, tsp_milliseconds)
+    })
+    .await
+    {
+        Ok(repaired) => repaired,
+        Err(_) => {
+            quality.repair = ShapeRepair::Failed;
+            return (line, quality);
+        }
+    };
+    let (repaired_length, _, repaired_ratio) = length_to_diameter(&repaired);
+    quality.repaired_ratio = Some(repaired_ratio);
+
+    if is_broken(repaired_length, repaired_ratio, thresholds) {
+        quality.repair = ShapeRepair::Failed;
+        (line, quality)
+    } else {
+        quality.repair = ShapeRepair::TravellingSalesman;
+        (repaired, quality)
+    }
 }
 
 fn abs_area(p: geo::Point, q: geo::Point, r: geo::Point) -> f64 {
@@ -0,0 +1,26 @@
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+//for files gtfs_structures doesn't parse or drops columns from
+//a missing file is an empty list, a broken row is an error for the whole file
+pub fn read_optional_gtfs_file<T: DeserializeOwned>(
+    folder: &str,
+    file_name: &str,
+) -> Result<Vec<T>, String> {
+    let path = Path::new(folder).join(file_name);
+
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(&path)
+        .map_err(|err| format!("{}: {}", file_name, err))?;
+
+    reader
+        .deserialize::<T>()
+        .map(|row| row.map_err(|err| format!("{}: {}", file_name, err)))
+        .collect()
+}
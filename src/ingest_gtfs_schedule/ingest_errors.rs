@@ -0,0 +1,55 @@
+use tokio_postgres::Client;
+
+//a problem with one feed, written to gtfs_errors instead of taking down the whole import
+#[derive(Debug, Clone)]
+pub struct IngestError {
+    //which part of the import it happened in, eg read, shapes, trips, stoptimes, stops, hull, commit
+    pub stage: &'static str,
+    pub message: String,
+}
+
+impl IngestError {
+    pub fn new(stage: &'static str, message: String) -> IngestError {
+        IngestError { stage, message }
+    }
+}
+
+pub trait IngestStage<T> {
+    fn stage(self, stage: &'static str) -> Result<T, IngestError>;
+}
+
+impl<T, E: std::fmt::Display> IngestStage<T> for Result<T, E> {
+    fn stage(self, stage: &'static str) -> Result<T, IngestError> {
+        self.map_err(|err| IngestError::new(stage, format!("{}", err)))
+    }
+}
+
+//replaces whatever was recorded for this feed on the last run
+pub async fn write_feed_errors(
+    client: &Client,
+    schemaname: &str,
+    feed_id: &str,
+    errors: &[IngestError],
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            format!("DELETE FROM {schemaname}.gtfs_errors WHERE onestop_feed_id = $1;").as_str(),
+            &[&feed_id],
+        )
+        .await?;
+
+    let statement = client
+        .prepare(
+            format!("INSERT INTO {schemaname}.gtfs_errors (onestop_feed_id, stage, error, recorded_at) VALUES ($1, $2, $3, now());")
+                .as_str(),
+        )
+        .await?;
+
+    for error in errors {
+        client
+            .execute(&statement, &[&feed_id, &error.stage, &error.message])
+            .await?;
+    }
+
+    Ok(())
+}
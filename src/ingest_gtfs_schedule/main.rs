@@ -1,8 +1,6 @@
-use bb8::PooledConnection;
 use geo::CoordsIter;
 use gtfs_structures::LocationType;
 use gtfs_structures::Route;
-use gtfs_structures::Trip;
 use itertools::Itertools;
 use serde::Serialize;
 use serde_json::Error as SerdeError;
@@ -11,7 +9,9 @@ use std::collections::HashMap;
 use std::fs;
 use titlecase::titlecase;
 use tokio_postgres::Statement;
-mod dmfr;
+use tokio_postgres::Transaction;
+use aspenlib::dmfr;
+use ingest_errors::{IngestError, IngestStage};
 use bb8_postgres::PostgresConnectionManager;
 use futures;
 use geo_postgis::ToPostgis;
@@ -33,11 +33,24 @@ extern crate fs_extra;
 use fs_extra::dir::get_size;
 
 mod colour_correction;
+mod colour_overrides;
 mod convex_hull;
+mod copy_upload;
 
+mod feed_hash;
+mod feed_manifest;
+mod feed_timezone;
+mod fares;
 mod fix_broken_lines;
+mod gtfs_csv;
+mod ingest_errors;
 mod make_prod_index;
+mod service_calendar;
+mod shape_clipping;
 mod shape_functions;
+mod station_navigation;
+mod stop_time_interpolation;
+mod synthetic_shapes;
 struct RealtimeOverride {
     realtimeid: String,
     operatorid: String,
@@ -243,6 +256,77 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .get::<bool>("forcewipe")
         .unwrap_or_else(|| false);
 
+    //only re-ingest the feeds transitlanddownload marked as changed in gtfs_static_zips/manifest.json
+    let onlychangedfeeds = arguments::parse(std::env::args())
+        .unwrap()
+        .get::<bool>("onlychangedfeeds")
+        .unwrap_or_else(|| false);
+
+    //re-import feeds even when their content hash matches what is already loaded, eg after changing colour corrections
+    let ignorehash = arguments::parse(std::env::args())
+        .unwrap()
+        .get::<bool>("ignorehash")
+        .unwrap_or_else(|| false);
+
+    //per feed and per route colour fixes, edit the json instead of colour_correction.rs
+    let colouroverrides = arguments::parse(std::env::args())
+        .unwrap()
+        .get::<String>("colouroverrides")
+        .unwrap_or_else(|| String::from(colour_overrides::COLOUR_OVERRIDES_PATH));
+
+    let colour_overrides = match colour_overrides::read_colour_overrides(&colouroverrides) {
+        Ok(colour_overrides) => {
+            println!("Loaded {} colour overrides", colour_overrides.overrides.len());
+            Arc::new(colour_overrides)
+        }
+        Err(err) => panic!("Could not read colour overrides: {}", err),
+    };
+
+    //polygons and cut points that trim railyard and deadhead tails off shapes
+    let shapeclipping = arguments::parse(std::env::args())
+        .unwrap()
+        .get::<String>("shapeclipping")
+        .unwrap_or_else(|| String::from(shape_clipping::SHAPE_CLIPPING_PATH));
+
+    let shape_clipping = match shape_clipping::read_shape_clipping(&shapeclipping) {
+        Ok(shape_clipping) => {
+            println!("Loaded {} shape clipping rules", shape_clipping.rules.len());
+            Arc::new(shape_clipping)
+        }
+        Err(err) => panic!("Could not read shape clipping rules: {}", err),
+    };
+
+    //shapes longer than brokenshaperatio times the distance they span are treated as scrambled and reordered
+    let default_thresholds = fix_broken_lines::BrokenShapeThresholds::default();
+    let broken_shape_thresholds = Arc::new(fix_broken_lines::BrokenShapeThresholds {
+        max_ratio: arguments::parse(std::env::args())
+            .unwrap()
+            .get::<f64>("brokenshaperatio")
+            .unwrap_or(default_thresholds.max_ratio),
+        min_length: arguments::parse(std::env::args())
+            .unwrap()
+            .get::<f64>("brokenshapeminlength")
+            .unwrap_or(default_thresholds.min_length),
+        tsp_milliseconds: arguments::parse(std::env::args())
+            .unwrap()
+            .get::<i64>("tspmilliseconds")
+            .unwrap_or(default_thresholds.tsp_milliseconds),
+    });
+
+    let changed_feeds: Option<HashSet<String>> = match onlychangedfeeds {
+        true => match feed_manifest::read_manifest() {
+            Some(manifest) => {
+                println!("Manifest lists {} changed feeds", manifest.changed.len());
+                Some(manifest.changed.into_iter().collect())
+            }
+            None => panic!(
+                "--onlychangedfeeds needs {}, run transitlanddownload first",
+                feed_manifest::MANIFEST_PATH
+            ),
+        },
+        false => None,
+    };
+
     if (startfresh.unwrap_or(false) && isprod.unwrap_or(false) && forcewipe) {
         panic!("Cannot wipe the prod server without --forcewipe true");
     }
@@ -274,6 +358,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             "
         CREATE EXTENSION IF NOT EXISTS postgis;
         CREATE EXTENSION IF NOT EXISTS hstore;
+        CREATE EXTENSION IF NOT EXISTS pg_trgm;
     ",
         )
         .await
@@ -302,9 +387,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
             format!(
                 "
         CREATE TABLE IF NOT EXISTS {schemaname}.gtfs_errors (
-            onestop_feed_id text PRIMARY KEY,
-            error text
-        )"
+            onestop_feed_id text NOT NULL,
+            stage text,
+            error text,
+            recorded_at timestamptz
+        );
+        ALTER TABLE {schemaname}.gtfs_errors DROP CONSTRAINT IF EXISTS gtfs_errors_pkey;
+        ALTER TABLE {schemaname}.gtfs_errors ADD COLUMN IF NOT EXISTS stage text;
+        ALTER TABLE {schemaname}.gtfs_errors ADD COLUMN IF NOT EXISTS recorded_at timestamptz;
+        CREATE INDEX IF NOT EXISTS gtfs_errors_feed ON {schemaname}.gtfs_errors (onestop_feed_id);"
             )
             .as_str(),
         )
@@ -318,8 +409,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
         CREATE TABLE IF NOT EXISTS {schemaname}.feeds_updated (
             onestop_feed_id text PRIMARY KEY,
             created_trips boolean,
-            updated_trips_time_ms bigint
-        );"
+            updated_trips_time_ms bigint,
+            content_hash text
+        );
+        ALTER TABLE {schemaname}.feeds_updated ADD COLUMN IF NOT EXISTS content_hash text;"
             )
             .as_str(),
         )
@@ -341,9 +434,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
             max_lon double precision NOT NULL,
             min_lat double precision NOT NULL,
             min_lon double precision NOT NULL,
-            hull GEOMETRY(POLYGON,4326) NOT NULL
-        );",
-                schemaname
+            hull GEOMETRY(POLYGON,4326) NOT NULL,
+            default_timezone text
+        );
+        ALTER TABLE {}.static_feeds ADD COLUMN IF NOT EXISTS default_timezone text;",
+                schemaname, schemaname
             )
             .as_str(),
         )
@@ -381,9 +476,19 @@ async fn main() -> Result<(), Box<dyn Error>> {
             max_lat double precision,
             max_lon double precision,
             min_lat double precision,
-            min_lon double precision
-        );",
-                schemaname
+            min_lon double precision,
+            realtime_vehicle_positions text,
+            realtime_trip_updates text,
+            realtime_alerts text,
+            authorization_type text,
+            authorization_param_name text
+        );
+        ALTER TABLE {}.realtime_feeds ADD COLUMN IF NOT EXISTS realtime_vehicle_positions text;
+        ALTER TABLE {}.realtime_feeds ADD COLUMN IF NOT EXISTS realtime_trip_updates text;
+        ALTER TABLE {}.realtime_feeds ADD COLUMN IF NOT EXISTS realtime_alerts text;
+        ALTER TABLE {}.realtime_feeds ADD COLUMN IF NOT EXISTS authorization_type text;
+        ALTER TABLE {}.realtime_feeds ADD COLUMN IF NOT EXISTS authorization_param_name text;",
+                schemaname, schemaname, schemaname, schemaname, schemaname, schemaname
             )
             .as_str(),
         )
@@ -447,9 +552,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
             continuous_drop_off smallint,
             point GEOMETRY(POINT,4326) NOT NULL,
             route_id text,
+            interpolated boolean NOT NULL DEFAULT false,
             PRIMARY KEY (onestop_feed_id, trip_id, stop_sequence)
-        )",
-                schemaname
+        );
+        ALTER TABLE {}.stoptimes ADD COLUMN IF NOT EXISTS interpolated boolean NOT NULL DEFAULT false;",
+                schemaname, schemaname
             )
             .as_str(),
         )
@@ -475,9 +582,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
             continuous_pickup smallint,
             continuous_drop_off smallint,
             shapes_list text[],
+            dark_color text,
             PRIMARY KEY (onestop_feed_id, route_id)
-        );",
-                schemaname
+        );
+        ALTER TABLE {}.routes ADD COLUMN IF NOT EXISTS dark_color text;",
+                schemaname, schemaname
             )
             .as_str(),
         )
@@ -497,9 +606,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
             route_type smallint NOT NULL,
             route_label text,
             text_color text,
+            dark_color text,
+            synthetic boolean NOT NULL DEFAULT false,
             PRIMARY KEY (onestop_feed_id,shape_id)
-        );",
-                schemaname
+        );
+        ALTER TABLE {}.shapes ADD COLUMN IF NOT EXISTS dark_color text;
+        ALTER TABLE {}.shapes ADD COLUMN IF NOT EXISTS synthetic boolean NOT NULL DEFAULT false;",
+                schemaname, schemaname, schemaname
             )
             .as_str(),
         )
@@ -533,6 +646,255 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .await
         .unwrap();
 
+    client
+        .batch_execute(
+            format!(
+                "
+        CREATE TABLE IF NOT EXISTS {schemaname}.calendar (
+            onestop_feed_id text NOT NULL,
+            service_id text NOT NULL,
+            monday boolean NOT NULL,
+            tuesday boolean NOT NULL,
+            wednesday boolean NOT NULL,
+            thursday boolean NOT NULL,
+            friday boolean NOT NULL,
+            saturday boolean NOT NULL,
+            sunday boolean NOT NULL,
+            gtfs_start_date date NOT NULL,
+            gtfs_end_date date NOT NULL,
+            PRIMARY KEY (onestop_feed_id, service_id)
+        );"
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+
+    client
+        .batch_execute(
+            format!(
+                "
+        CREATE TABLE IF NOT EXISTS {schemaname}.calendar_dates (
+            onestop_feed_id text NOT NULL,
+            service_id text NOT NULL,
+            gtfs_date date NOT NULL,
+            exception_type smallint NOT NULL,
+            PRIMARY KEY (onestop_feed_id, service_id, gtfs_date)
+        );"
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+
+    client
+        .batch_execute(
+            format!(
+                "
+        CREATE TABLE IF NOT EXISTS {schemaname}.service_days (
+            onestop_feed_id text NOT NULL,
+            service_id text NOT NULL,
+            active_dates date[] NOT NULL,
+            PRIMARY KEY (onestop_feed_id, service_id)
+        );"
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+
+    client
+        .batch_execute(
+            format!(
+                "
+        CREATE TABLE IF NOT EXISTS {schemaname}.agencies (
+            onestop_feed_id text NOT NULL,
+            agency_id text NOT NULL,
+            agency_name text NOT NULL,
+            agency_url text,
+            agency_timezone text,
+            agency_lang text,
+            agency_phone text,
+            agency_fare_url text,
+            agency_email text,
+            onestop_operator_id text,
+            PRIMARY KEY (onestop_feed_id, agency_id)
+        );"
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+
+    client
+        .batch_execute(
+            format!(
+                "
+        CREATE TABLE IF NOT EXISTS {schemaname}.frequencies (
+            onestop_feed_id text NOT NULL,
+            trip_id text NOT NULL,
+            start_time int NOT NULL,
+            end_time int NOT NULL,
+            headway_secs int NOT NULL,
+            exact_times boolean NOT NULL,
+            PRIMARY KEY (onestop_feed_id, trip_id, start_time)
+        );
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.levels (
+            onestop_feed_id text NOT NULL,
+            level_id text NOT NULL,
+            level_index double precision NOT NULL,
+            level_name text,
+            PRIMARY KEY (onestop_feed_id, level_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.pathways (
+            onestop_feed_id text NOT NULL,
+            pathway_id text NOT NULL,
+            from_stop_id text NOT NULL,
+            to_stop_id text NOT NULL,
+            pathway_mode smallint NOT NULL,
+            is_bidirectional boolean NOT NULL,
+            length double precision,
+            traversal_time int,
+            stair_count int,
+            max_slope double precision,
+            min_width double precision,
+            signposted_as text,
+            reversed_signposted_as text,
+            PRIMARY KEY (onestop_feed_id, pathway_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS pathways_from_stop ON {schemaname}.pathways (onestop_feed_id, from_stop_id);
+        CREATE INDEX IF NOT EXISTS pathways_to_stop ON {schemaname}.pathways (onestop_feed_id, to_stop_id);
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.transfers (
+            onestop_feed_id text NOT NULL,
+            from_stop_id text,
+            to_stop_id text,
+            from_route_id text,
+            to_route_id text,
+            from_trip_id text,
+            to_trip_id text,
+            transfer_type smallint NOT NULL,
+            min_transfer_time int
+        );
+
+        CREATE INDEX IF NOT EXISTS transfers_from_stop ON {schemaname}.transfers (onestop_feed_id, from_stop_id);
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.fare_attributes (
+            onestop_feed_id text NOT NULL,
+            fare_id text NOT NULL,
+            price double precision NOT NULL,
+            currency_type text NOT NULL,
+            payment_method smallint NOT NULL,
+            transfers smallint,
+            agency_id text,
+            transfer_duration int,
+            PRIMARY KEY (onestop_feed_id, fare_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.fare_rules (
+            onestop_feed_id text NOT NULL,
+            fare_id text NOT NULL,
+            route_id text,
+            origin_id text,
+            destination_id text,
+            contains_id text
+        );
+
+        CREATE INDEX IF NOT EXISTS fare_rules_feed ON {schemaname}.fare_rules (onestop_feed_id);
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.fare_products (
+            onestop_feed_id text NOT NULL,
+            fare_product_id text NOT NULL,
+            fare_product_name text,
+            fare_media_id text,
+            amount double precision NOT NULL,
+            currency text NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS fare_products_feed ON {schemaname}.fare_products (onestop_feed_id, fare_product_id);
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.fare_leg_rules (
+            onestop_feed_id text NOT NULL,
+            leg_group_id text,
+            network_id text,
+            from_area_id text,
+            to_area_id text,
+            from_timeframe_group_id text,
+            to_timeframe_group_id text,
+            fare_product_id text NOT NULL,
+            rule_priority int
+        );
+
+        CREATE INDEX IF NOT EXISTS fare_leg_rules_feed ON {schemaname}.fare_leg_rules (onestop_feed_id);
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.fare_transfer_rules (
+            onestop_feed_id text NOT NULL,
+            from_leg_group_id text,
+            to_leg_group_id text,
+            transfer_count int,
+            duration_limit int,
+            duration_limit_type smallint,
+            fare_transfer_type smallint NOT NULL,
+            fare_product_id text
+        );
+
+        CREATE INDEX IF NOT EXISTS fare_transfer_rules_feed ON {schemaname}.fare_transfer_rules (onestop_feed_id);
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.areas (
+            onestop_feed_id text NOT NULL,
+            area_id text NOT NULL,
+            area_name text,
+            PRIMARY KEY (onestop_feed_id, area_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.stop_areas (
+            onestop_feed_id text NOT NULL,
+            area_id text NOT NULL,
+            stop_id text NOT NULL,
+            PRIMARY KEY (onestop_feed_id, area_id, stop_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS stop_areas_stop ON {schemaname}.stop_areas (onestop_feed_id, stop_id);
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.shape_quality (
+            onestop_feed_id text NOT NULL,
+            shape_id text NOT NULL,
+            length_m double precision NOT NULL,
+            diameter_m double precision NOT NULL,
+            ratio double precision NOT NULL,
+            repair text NOT NULL,
+            repaired_ratio double precision,
+            PRIMARY KEY (onestop_feed_id, shape_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.route_networks (
+            onestop_feed_id text NOT NULL,
+            route_id text NOT NULL,
+            network_id text NOT NULL,
+            PRIMARY KEY (onestop_feed_id, route_id, network_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS {schemaname}.feed_info (
+            onestop_feed_id text PRIMARY KEY,
+            feed_publisher_name text,
+            feed_publisher_url text,
+            feed_lang text,
+            default_lang text,
+            feed_start_date date,
+            feed_end_date date,
+            feed_version text,
+            feed_contact_email text,
+            feed_contact_url text
+        );"
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+
     if is_prod.unwrap_or(false) {
         println!("making martin functions");
         make_prod_index::make_prod_index(&client, &schemaname.to_string()).await;
@@ -756,11 +1118,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .unwrap();
 
     let mut handles = vec![];
+    //feeds that are loaded and up to date, cleared from the manifest's changed list at the end
+    let imported_feeds: Arc<std::sync::Mutex<HashSet<String>>> =
+        Arc::new(std::sync::Mutex::new(HashSet::new()));
     println!("run db upload now");
     println!("limittostaticfeed {:?}", &limittostaticfeed);
     let client = pool.get().await.unwrap();
     for (key, feed) in feedhashmap.to_owned().into_iter() {
         let pool = pool.to_owned();
+        let colour_overrides = colour_overrides.to_owned();
+        let shape_clipping = shape_clipping.to_owned();
+        let broken_shape_thresholds = broken_shape_thresholds.to_owned();
+        let imported_feeds = imported_feeds.to_owned();
         let mut dothetask = true;
         if feeds_to_discard.contains(&key.as_str()) {
             dothetask = false;
@@ -772,10 +1141,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 //println!("Cancelled because limit to static feed");
             }
         }
+        if let Some(changed_feeds) = &changed_feeds {
+            if feed.spec == dmfr::FeedSpec::Gtfs && !changed_feeds.contains(&key) {
+                dothetask = false;
+            }
+        }
         if soft_insert == Some(true) {
             let already_done = client.query(format!("SELECT onestop_feed_id, created_trips, updated_trips_time_ms FROM {schemaname}.feeds_updated WHERE onestop_feed_id = $1;").as_str(),
              &[&feed.id])
-             .await.unwrap();
+             .await.unwrap_or_default();
             if already_done.len() == 1 {
                 dothetask = false;
                 // println!("Already done {}", &feed.id);
@@ -795,7 +1169,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
         handles.push(threaded_rt.spawn(async move 
             {
                 //it timesout here a lot
-                let client = pool.get().await.unwrap();
+                let mut client = match pool.get().await {
+                    Ok(client) => client,
+                    Err(err) => {
+                        println!("Could not get a connection for {}: {}", &key, err);
+                        return;
+                    }
+                };
     
                 //println!("Feed in future {}: {:#?}", key, feed);
     
@@ -816,11 +1196,31 @@ async fn main() -> Result<(), Box<dyn Error>> {
                             if path_exists(&file_path) {
     
                                 //feed exists
+
+                                let content_hash = match feed_hash::hash_feed_folder(&file_path) {
+                                    Ok(content_hash) => Some(content_hash),
+                                    Err(err) => {
+                                        println!("Could not hash {}: {}", &key, err);
+                                        None
+                                    }
+                                };
+
+                                if ignorehash == false && content_hash.is_some() {
+                                    let loaded_hash = client.query(format!("SELECT content_hash FROM {schemaname}.feeds_updated WHERE onestop_feed_id = $1;").as_str(),
+                                        &[&feed.id])
+                                        .await.unwrap_or_default();
+
+                                    if loaded_hash.len() == 1 && loaded_hash[0].get::<_, Option<String>>(0) == content_hash {
+                                        println!("{} is unchanged since the last import, skipping", &key);
+                                        imported_feeds.lock().unwrap().insert(key.to_owned());
+                                        return;
+                                    }
+                                }
     
                                 println!("Starting read for {}", &key);
     
                                 
-                            let folder_size = get_size(&file_path).unwrap();
+                            let folder_size = get_size(&file_path).unwrap_or(0);
                             println!("size: {} kB", folder_size / 1000); 
     
                                 let gtfs = gtfs_structures::GtfsReader::default()
@@ -829,6 +1229,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                 match gtfs 
                                 {
                                 Ok(gtfs) => {
+
+                                    //problems that don't stop the feed from loading, like shapes with one point
+                                    let mut feed_errors: Vec<IngestError> = vec![];
+
+                                    let ingest_result = async {
     
                                     println!("read_duration: {:?}ms", gtfs.read_duration);
     
@@ -848,6 +1253,20 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                     let mut most_lat: Option<f64> = None;
                                     let mut most_lon: Option<f64> = None;
 
+                                    //everything for this feed is replaced inside one transaction so prod never serves half an agency
+                                    let client = client.transaction().await.stage("delete_old_rows")?;
+
+                                    for table in ["stops", "routes", "shapes", "calendar", "calendar_dates", "service_days", "agencies", "feed_info", "levels", "pathways", "transfers",
+                                        "fare_attributes", "fare_rules", "fare_products", "fare_leg_rules", "fare_transfer_rules", "areas", "stop_areas", "route_networks", "shape_quality"] {
+                                        client.execute(format!("DELETE FROM {schemaname}.{table} WHERE onestop_feed_id = $1;").as_str(), &[&feed.id]).await.stage("delete_old_rows")?;
+                                    }
+
+                                    if skiptrips == false {
+                                        for table in ["stoptimes", "trips", "frequencies"] {
+                                            client.execute(format!("DELETE FROM {schemaname}.{table} WHERE onestop_feed_id = $1;").as_str(), &[&feed.id]).await.stage("delete_old_rows")?;
+                                        }
+                                    }
+
                                     let (stop_ids_to_route_types,stop_ids_to_route_ids) = make_hashmap_stops_to_route_types_and_ids(&gtfs);
     
                                     let (stop_id_to_children_ids, stop_ids_to_children_route_types) = make_hashmaps_of_children_stop_info(&gtfs,&stop_ids_to_route_types,&stop_ids_to_route_ids);
@@ -914,7 +1333,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                                         .get(&trip.route_id)
                                                         .unwrap();
     
-                                                    let color = colour_correction::fix_background_colour_rgb_feed_route(&feed.id,route.color,route);
+                                                    let color = colour_correction::fix_background_colour_rgb_feed_route(&colour_overrides, &feed.id,route.color,route);
     
                                                     shape_to_color_lookup.insert(
                                                     trip.shape_id.as_ref().unwrap().to_owned(),
@@ -922,23 +1341,71 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                                     );
                                                     shape_to_text_color_lookup.insert(
                                                         trip.shape_id.as_ref().unwrap().to_owned(),
-                                                        route.text_color,
+                                                        colour_correction::fix_foreground_colour_rgb_feed(&colour_overrides, &feed.id, route, color, route.text_color),
                                                     );
                                                 }
                                             }
                                         }
                                     }
     
-                                   let prepared_shapes = client.prepare(format!("INSERT INTO {schemaname}.shapes
-                                    (onestop_feed_id, shape_id, linestring, color, text_color, routes, route_type,route_label) 
-                                    VALUES ($1, $2, $3, $4, $5, $6,$7,$8) ON CONFLICT (onestop_feed_id, shape_id) DO UPDATE set
-                                    linestring = $3,
-                                    color = $4,
-                                    text_color = $5,
-                                    routes = $6,
-                                    route_type = $7,
-                                    route_label = $8
-                                    ;").as_str()).await.unwrap();
+                                    let synthetic_shapes = synthetic_shapes::make_synthetic_shapes(&gtfs);
+                                    if synthetic_shapes.len() > 0 {
+                                        println!("Made {} synthetic shapes from stop patterns for {}", synthetic_shapes.len(), &feed.id);
+                                    }
+                                    let trip_to_synthetic_shape: HashMap<&String, &String> = synthetic_shapes.iter()
+                                        .flat_map(|synthetic_shape| synthetic_shape.trip_ids.iter().map(move |trip_id| (trip_id, &synthetic_shape.shape_id)))
+                                        .collect();
+
+                                    //old rows were deleted at the start of the transaction, so COPY can write straight in
+                                    let shapes_writer = copy_upload::start_binary_copy(&client, schemaname, "shapes",
+                                        &["onestop_feed_id", "shape_id", "linestring", "color", "text_color", "routes", "route_type", "route_label", "dark_color", "synthetic"]).await.stage("shapes")?;
+                                    futures::pin_mut!(shapes_writer);
+
+                                    //rule index -> (points removed, shapes it matched)
+                                    let mut clipped_points_per_rule: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+
+                                    //the stops of the longest trip on each shape, used to put the points of a scrambled shape back in order
+                                    let mut shape_to_stop_points: HashMap<&String, Vec<geo::Point>> = HashMap::new();
+                                    for trip in gtfs.trips.values() {
+                                        if let Some(shape_id) = &trip.shape_id {
+                                            let stop_points = trip.stop_times.iter()
+                                                .filter_map(|stoptime| match (stoptime.stop.longitude, stoptime.stop.latitude) {
+                                                    (Some(lon), Some(lat)) => Some(geo::Point::new(lon, lat)),
+                                                    _ => None,
+                                                })
+                                                .collect::<Vec<geo::Point>>();
+                                            if shape_to_stop_points.get(shape_id).map_or(true, |existing| existing.len() < stop_points.len()) {
+                                                shape_to_stop_points.insert(shape_id, stop_points);
+                                            }
+                                        }
+                                    }
+
+                                    let mut shape_qualities: Vec<(&String, fix_broken_lines::ShapeQuality)> = vec![];
+
+                                    //short names of the routes on a shape, eg "720,20"
+                                    let make_route_label = |route_ids: &Vec<String>| -> String {
+                                           route_ids.iter().map(|route_id| {
+                                            let route = gtfs.routes.get(route_id);
+                                            if route.is_some() {
+                                                if route.unwrap().short_name.as_str() == "" {
+                                                  if route.unwrap().long_name.as_str() == "" {
+                                                    return route_id.to_string();
+                                                  } else {
+                                                    return route.unwrap().long_name.to_owned()
+                                                    .replace("-16168","")
+                                                    .replace("Counterclockwise", "ACW").replace("counterclockwise", "ACW").replace("clockwise", "CW").replace("Clockwise", "CW");
+                                                  }
+                                                } else {
+                                                    return route.unwrap().short_name.to_owned()
+                                                    .replace("-16168","")
+                                                    .replace("Counterclockwise", "ACW").replace("counterclockwise", "ACW").replace("clockwise", "CW").replace("Clockwise", "CW");
+                                                }
+                                                
+                                            } else {
+                                                return route_id.to_string();
+                                            }
+                                           }).collect::<Vec<String>>().join(",").as_str().replace("Orange County","OC").replace("Inland Empire", "IE").to_string()
+                                    };
 
                                     for (shape_id, shape) in &gtfs.shapes {
                                         let mut route_ids: Vec<String> = match gtfs
@@ -990,15 +1457,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                         let color_to_upload =
                                         match feed.id.as_str() {
                                             "f-9qh-metrolinktrains" => {
-                                                if route_ids.len() > 0 {
-                                                    let route = gtfs.routes.get(&route_ids[0]);
-                                                    let color = route.unwrap().color;
-                                                    format!(
+                                                match route_ids.first().and_then(|route_id| gtfs.routes.get(route_id)) {
+                                                    Some(route) => format!(
                                                         "{:02x}{:02x}{:02x}",
-                                                        color.r, color.g, color.b
-                                                    )
-                                                } else {
-                                                    String::from("3a3a3a")
+                                                        route.color.r, route.color.g, route.color.b
+                                                    ),
+                                                    None => String::from("3a3a3a"),
                                                 }
                                             },
                                             _ => {
@@ -1011,126 +1475,177 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                                 }
                                             }
                                         };
+                                        let (clipped, removed_per_rule) = shape_clipping.apply(
+                                            &feed.id,
+                                            shape_id,
+                                            &route_ids,
+                                            color_to_upload.as_str(),
+                                            geo::LineString::from(shape.iter().map(|point| (point.longitude, point.latitude)).collect::<Vec<(f64, f64)>>()),
+                                        );
+                                        for (rule_index, removed) in removed_per_rule {
+                                            let entry = clipped_points_per_rule.entry(rule_index).or_insert((0, 0));
+                                            entry.0 = entry.0 + removed;
+                                            entry.1 = entry.1 + 1;
+                                        }
+
                                         //bug "Line String must at least have 2 points"
-                                        let preshape = shape
-                                        .iter()
-                                        .filter(|point| {
-                                            match feed.id.as_str() {
-                                                "f-9q5-metro~losangeles~rail" => {
-                                                    //remove B/D railyard
-                                                    match color_to_upload.as_str() {
-                                                        "eb131b" => {
-                                                            point.longitude < -118.2335698
-                                                        }
-                                                        "a05da5" => {
-                                                            point.longitude < -118.2335698
-                                                        },
-                                                        "e470ab" => {
-                                                            point.latitude > 33.961543
-                                                        }
-                                                        _ => true,
-                                                    }
-                                                }
-                                                _ => true,
-                                            }
-                                        })
-                                        .filter(|point| {
-                                            match route_ids.len() {
-                                                1 => {
-                                                    //remove B/D railyard
-                                                    match route_ids[0].as_str() {
-                                                        "807" => {
-                                                            point.latitude > 33.961543
-                                                        }
-                                                        _ => true,
-                                                    }
-                                                }
-                                                _ => true,
-                                            }
-                                        })
-                                    ;
-                                        if preshape.to_owned().count() < 2 {
+                                        if clipped.0.len() < 2 {
                                             println!("Shape {} has less than 2 points", shape_id);
+                                            feed_errors.push(IngestError::new("shapes", format!("shape {} has less than 2 points", shape_id)));
                                             continue;
                                         }
+
+                                        let (clipped, shape_quality) = fix_broken_lines::fix_broken_lines(
+                                            clipped,
+                                            shape_to_stop_points.get(shape_id).map(|stop_points| stop_points.as_slice()).unwrap_or(&[]),
+                                            &broken_shape_thresholds,
+                                        ).await;
+                                        if shape_quality.repair != fix_broken_lines::ShapeRepair::NotNeeded {
+                                            shape_qualities.push((shape_id, shape_quality));
+                                        }
                                         let linestring = ewkb::LineStringT {
                                             srid: Some(4326),
                                             points: 
-                                                preshape.map(|point| ewkb::Point {
-                                                    x: point.longitude,
-                                                    y: point.latitude,
+                                                clipped.points().map(|point| ewkb::Point {
+                                                    x: point.x(),
+                                                    y: point.y(),
                                                     srid: Some(4326),
                                                 })
                                                 .collect(),
                                         };
     
+                                        //the lookup already holds the colour overrides, or the generic fixes when none matched
+                                        //only colours that didn't come from it are corrected here, so an override is never replaced
+                                        let background_color = match (feed.id.as_str(), shape_to_color_lookup.contains_key(shape_id)) {
+                                            ("f-9qh-metrolinktrains", _) | (_, false) => colour_correction::fix_background_colour(color_to_upload.as_str()).to_string(),
+                                            _ => color_to_upload.to_owned(),
+                                        };
+
                                         let text_color = match feed.id.as_str() {
                                             "f-9qh-metrolinktrains" => {
-                                                if route_ids.len() > 0 {
-                                                    let route = gtfs.routes.get(&route_ids[0]);
-                                                    let text_color = route.unwrap().text_color;
-                                                    format!(
+                                                match route_ids.first().and_then(|route_id| gtfs.routes.get(route_id)) {
+                                                    Some(route) => colour_correction::fix_foreground_colour(background_color.as_str(), format!(
                                                         "{:02x}{:02x}{:02x}",
-                                                        text_color.r, text_color.g, text_color.b
-                                                    )
-                                                } else {
-                                                    String::from("ffffff")
+                                                        route.text_color.r, route.text_color.g, route.text_color.b
+                                                    ).as_str()),
+                                                    None => colour_correction::fix_foreground_colour(background_color.as_str(), "ffffff"),
                                                 }
                                             },
                                             "f-9-amtrak~amtrakcalifornia~amtrakcharteredvehicle" => {
-                                                String::from("ffffff")
+                                                colour_correction::fix_foreground_colour(background_color.as_str(), "ffffff")
                                             },
                                             _ => {
                                                 match shape_to_text_color_lookup.get(shape_id) {
-                                                    Some(color) => format!(
-                                                        "{:02x}{:02x}{:02x}",
-                                                        color.r, color.g, color.b
-                                                    ),
-                                                    None => String::from("000000"),
+                                                    Some(color) => colour_correction::hex_colour(*color),
+                                                    None => colour_correction::fix_foreground_colour(background_color.as_str(), "000000"),
                                                 }
                                             }
                                         };
     
                                            // println!("uploading shape {:?} {:?}", &feed.id, &shape_id);
-                                           let route_label:String = route_ids.iter().map(|route_id| {
-                                            let route = gtfs.routes.get(route_id);
-                                            if route.is_some() {
-                                                if route.unwrap().short_name.as_str() == "" {
-                                                  if route.unwrap().long_name.as_str() == "" {
-                                                    return route_id.to_string();
-                                                  } else {
-                                                    return route.unwrap().long_name.to_owned()
-                                                    .replace("-16168","")
-                                                    .replace("Counterclockwise", "ACW").replace("counterclockwise", "ACW").replace("clockwise", "CW").replace("Clockwise", "CW");
-                                                  }
-                                                } else {
-                                                    return route.unwrap().short_name.to_owned()
-                                                    .replace("-16168","")
-                                                    .replace("Counterclockwise", "ACW").replace("counterclockwise", "ACW").replace("clockwise", "CW").replace("Clockwise", "CW");
-                                                }
-                                                
-                                            } else {
-                                                return route_id.to_string();
-                                            }
-                                           }).collect::<Vec<String>>().join(",").as_str().replace("Orange County","OC").replace("Inland Empire", "IE").to_string();
-    
-                                        client.query(&prepared_shapes,
+                                           let route_label = make_route_label(&route_ids);
+
+                                        shapes_writer.as_mut().write(
                                      &[
                                         &feed.id,
                                         &shape_id, 
                                      &linestring,
-                                     &colour_correction::fix_background_colour(color_to_upload.as_str()),
-                                     &colour_correction::fix_foreground_colour(color_to_upload.as_str(),text_color.as_str()),
+                                     &background_color,
+                                     &text_color,
                                      &route_ids,
                                      //add route type here
                                     &route_type_number,
-                                    &route_label
-                                     ]).await.unwrap();
+                                    &route_label,
+                                    &colour_correction::dark_map_colour(background_color.as_str()),
+                                    &false
+                                     ]).await.stage("shapes")?;
+                                    }
+
+                                    //feeds without shapes.txt would otherwise be missing from the map
+                                    for synthetic_shape in &synthetic_shapes {
+                                        let route = match gtfs.routes.get(&synthetic_shape.route_id) {
+                                            Some(route) => route,
+                                            None => continue,
+                                        };
+
+                                        let route_ids = vec![synthetic_shape.route_id.to_owned()];
+                                        let color = colour_correction::fix_background_colour_rgb_feed_route(&colour_overrides, &feed.id, route.color, route);
+                                        let text_color = colour_correction::fix_foreground_colour_rgb_feed(&colour_overrides, &feed.id, route, color, route.text_color);
+
+                                        let (clipped, _) = shape_clipping.apply(
+                                            &feed.id,
+                                            &synthetic_shape.shape_id,
+                                            &route_ids,
+                                            colour_correction::hex_colour(color).as_str(),
+                                            geo::LineString::from(synthetic_shape.points.to_owned()),
+                                        );
+
+                                        if clipped.0.len() < 2 {
+                                            continue;
+                                        }
+
+                                        let linestring = ewkb::LineStringT {
+                                            srid: Some(4326),
+                                            points: clipped.points().map(|point| ewkb::Point {
+                                                x: point.x(),
+                                                y: point.y(),
+                                                srid: Some(4326),
+                                            })
+                                            .collect(),
+                                        };
+
+                                        shapes_writer.as_mut().write(&[
+                                            &feed.id,
+                                            &synthetic_shape.shape_id,
+                                            &linestring,
+                                            &colour_correction::hex_colour(color),
+                                            &colour_correction::hex_colour(text_color),
+                                            &route_ids,
+                                            &route_type_to_int(&route.route_type),
+                                            &make_route_label(&route_ids),
+                                            &colour_correction::hex_colour(colour_correction::dark_map_colour_rgb(color)),
+                                            &true
+                                        ]).await.stage("shapes")?;
+                                    }
+
+                                    shapes_writer.finish().await.stage("shapes")?;
+
+                                    for (rule_index, (removed, shapes)) in &clipped_points_per_rule {
+                                        println!("Clipping rule {} removed {} points from {} shapes in {}", rule_index, removed, shapes, &feed.id);
                                     }
 
-                                    let routes: HashMap<(String, String), (&Route, &PooledConnection<PostgresConnectionManager<NoTls>>)> = gtfs.routes.iter()
+                                    //only scrambled shapes are written, an agency missing from shape_quality ships clean shapes
+                                    let shape_quality_statement = client.prepare(format!("INSERT INTO {schemaname}.shape_quality
+                                    (onestop_feed_id, shape_id, length_m, diameter_m, ratio, repair, repaired_ratio)
+                                    VALUES ($1, $2, $3, $4, $5, $6, $7);").as_str()).await.stage("shape_quality")?;
+
+                                    for (shape_id, shape_quality) in &shape_qualities {
+                                        client.query(&shape_quality_statement, &[
+                                            &feed.id,
+                                            shape_id,
+                                            &shape_quality.length,
+                                            &shape_quality.diameter,
+                                            &shape_quality.ratio,
+                                            &shape_quality.repair.as_str(),
+                                            &shape_quality.repaired_ratio
+                                        ]).await.stage("shape_quality")?;
+                                    }
+
+                                    if shape_qualities.len() > 0 {
+                                        let failed = shape_qualities.iter()
+                                            .filter(|(_, shape_quality)| shape_quality.repair == fix_broken_lines::ShapeRepair::Failed)
+                                            .map(|(shape_id, _)| shape_id.as_str())
+                                            .collect::<Vec<&str>>();
+                                        println!("{} of {} shapes in {} were scrambled, {} could not be repaired", shape_qualities.len(), gtfs.shapes.len(), &feed.id, failed.len());
+                                        if failed.len() > 0 {
+                                            feed_errors.push(IngestError::new("shape_quality", format!("{} scrambled shapes could not be repaired: {}", failed.len(), failed.iter().take(20).join(", "))));
+                                        }
+                                    }
+
+                                    let routes: HashMap<(String, String), (&Route, &Transaction)> = gtfs.routes.iter()
                                         .map(|(key, route)| ((key.to_owned(), feed.id.to_owned()), (route, &client))).collect();
                                     let routes_clone = routes.to_owned();
+                                    let colour_overrides: &colour_overrides::ColourOverrides = &colour_overrides;
                                     let route_workers = routes_clone.into_iter().map( |((route_id, feed_id), (route, client))| async move {
                                         let route_type_number = route_type_to_int(&route.route_type);
                                         let shapes_per_route: HashMap<String, Vec<String>> = HashMap::new();
@@ -1156,15 +1671,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                             text_color,
                                             continuous_pickup,
                                             continuous_drop_off,
-                                            shapes_list
+                                            shapes_list,
+                                            dark_color
                                         )
                                         VALUES (
-                                            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14
+                                            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15
                                         ) ON CONFLICT (onestop_feed_id, route_id) do update set 
                                         color = $10,
-                                        text_color = $11;
-                                        ").as_str()).await.unwrap();
+                                        text_color = $11,
+                                        dark_color = $15;
+                                        ").as_str()).await.stage("routes")?;
                                         let long_name = titlecase_process_new_nooption(&route.long_name);
+                                        let color = colour_correction::fix_background_colour_rgb_feed_route(colour_overrides, &feed_id, route.color, &route);
                                         client.query(
                                             &route_prepared,
                                             &[
@@ -1177,8 +1695,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                                 &route.url,
                                                 &route.agency_id,
                                                 &i32::try_from(route.order.unwrap_or_else(|| 0)).ok(),
-                                                &(colour_correction::fix_background_colour_rgb_feed_route(&feed_id,route.color, &route).to_string()),
-                                                &(colour_correction::fix_foreground_colour_rgb_feed(&feed_id, route.color, route.text_color).to_string()),
+                                                &colour_correction::hex_colour(color),
+                                                &colour_correction::hex_colour(colour_correction::fix_foreground_colour_rgb_feed(colour_overrides, &feed_id, &route, color, route.text_color)),
                                                 &(match route.continuous_pickup {
                                                     ContinuousPickupDropOff::Continuous => 0,
                                                     ContinuousPickupDropOff::NotAvailable => 1,
@@ -1194,37 +1712,218 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                                     ContinuousPickupDropOff::Unknown(i) => i,
                                                 }),
                                                 &shape_id_array,
+                                                &colour_correction::hex_colour(colour_correction::dark_map_colour_rgb(color)),
                                             ],
-                                        ).await.unwrap();
+                                        ).await.stage("routes")?;
+                                        Ok::<(), IngestError>(())
                                     });
                                     for worker in route_workers {
-                                        let _ = tokio::join!(worker);
+                                        worker.await?;
+                                    }
+                                    let default_timezone = feed_timezone::feed_default_timezone(&gtfs);
+                                    let invalid_agency_timezones = feed_timezone::invalid_agency_timezones(&gtfs, default_timezone.as_ref());
+                                    if invalid_agency_timezones.len() > 0 {
+                                        feed_errors.push(IngestError::new("agencies", format!("agency_timezone is invalid or differs from {}: {}", default_timezone.map(|timezone| timezone.name()).unwrap_or("nothing"), invalid_agency_timezones.join(", "))));
                                     }
+
+                                    let agency_statement = client.prepare(format!("INSERT INTO {schemaname}.agencies
+                                    (onestop_feed_id, agency_id, agency_name, agency_url, agency_timezone, agency_lang, agency_phone, agency_fare_url, agency_email, onestop_operator_id)
+                                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10);").as_str()).await.stage("agencies")?;
+
+                                    let mut written_agency_ids: HashSet<String> = HashSet::new();
+                                    let mut duplicate_agency_ids: Vec<String> = vec![];
+
+                                    for agency in &gtfs.agencies {
+                                        //agency_id is optional in feeds with a single agency
+                                        let agency_id = agency.id.to_owned().unwrap_or_default();
+
+                                        //several agencies without an id, or sharing one, would break the primary key and roll back the whole feed
+                                        if !written_agency_ids.insert(agency_id.to_owned()) {
+                                            duplicate_agency_ids.push(format!("{:?} ({})", agency_id, agency.name));
+                                            continue;
+                                        }
+
+                                        //dmfr either names the gtfs agency_id an operator covers, or leaves it empty when the operator is the whole feed
+                                        //the smallest operator id wins when several match, so every import picks the same one
+                                        let onestop_operator_id = operator_pairs_hashmap.iter()
+                                            .filter(|(_, gtfs_agency_id)| gtfs_agency_id.as_deref() == agency.id.as_deref() && gtfs_agency_id.is_some())
+                                            .map(|(operator_id, _)| operator_id)
+                                            .min()
+                                            .or_else(|| operator_pairs_hashmap.iter()
+                                                .filter(|(_, gtfs_agency_id)| gtfs_agency_id.is_none())
+                                                .map(|(operator_id, _)| operator_id)
+                                                .min())
+                                            .map(|operator_id| operator_id.to_owned());
+
+                                        client.query(&agency_statement, &[
+                                            &feed.id,
+                                            &agency_id,
+                                            &agency.name,
+                                            &agency.url,
+                                            &agency.timezone,
+                                            &agency.lang,
+                                            &agency.phone,
+                                            &agency.fare_url,
+                                            &agency.email,
+                                            &onestop_operator_id
+                                        ]).await.stage("agencies")?;
+                                    }
+
+                                    if duplicate_agency_ids.len() > 0 {
+                                        feed_errors.push(IngestError::new("agencies", format!("{} agencies skipped for reusing an agency_id, agencies without one count as \"\": {}", duplicate_agency_ids.len(), duplicate_agency_ids.join(", "))));
+                                    }
+
+                                    //feed_info.txt should only have one row
+                                    if let Some(feed_info) = gtfs.feed_info.first() {
+                                        client.query(format!("INSERT INTO {schemaname}.feed_info
+                                        (onestop_feed_id, feed_publisher_name, feed_publisher_url, feed_lang, default_lang, feed_start_date, feed_end_date, feed_version, feed_contact_email, feed_contact_url)
+                                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10);").as_str(), &[
+                                            &feed.id,
+                                            &feed_info.name,
+                                            &feed_info.url,
+                                            &feed_info.lang,
+                                            &feed_info.default_lang,
+                                            &feed_info.start_date,
+                                            &feed_info.end_date,
+                                            &feed_info.version,
+                                            &feed_info.contact_email,
+                                            &feed_info.contact_url
+                                        ]).await.stage("feed_info")?;
+                                    }
+
+                                    let levels = gtfs_csv::read_optional_gtfs_file::<station_navigation::RawLevel>(&file_path, "levels.txt")
+                                        .stage("station_navigation")?;
+                                    let pathways = gtfs_csv::read_optional_gtfs_file::<station_navigation::RawPathway>(&file_path, "pathways.txt")
+                                        .stage("station_navigation")?;
+                                    let transfers = gtfs_csv::read_optional_gtfs_file::<station_navigation::RawTransfer>(&file_path, "transfers.txt")
+                                        .stage("station_navigation")?;
+
+                                    println!("{} levels, {} pathways, {} transfers", levels.len(), pathways.len(), transfers.len());
+
+                                    let level_statement = client.prepare(format!("INSERT INTO {schemaname}.levels
+                                    (onestop_feed_id, level_id, level_index, level_name)
+                                    VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING;").as_str()).await.stage("station_navigation")?;
+
+                                    for level in &levels {
+                                        client.query(&level_statement, &[
+                                            &feed.id,
+                                            &level.level_id,
+                                            &level.level_index,
+                                            &level.level_name
+                                        ]).await.stage("station_navigation")?;
+                                    }
+
+                                    let pathway_statement = client.prepare(format!("INSERT INTO {schemaname}.pathways
+                                    (onestop_feed_id, pathway_id, from_stop_id, to_stop_id, pathway_mode, is_bidirectional, length, traversal_time, stair_count, max_slope, min_width, signposted_as, reversed_signposted_as)
+                                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) ON CONFLICT DO NOTHING;").as_str()).await.stage("station_navigation")?;
+
+                                    for pathway in &pathways {
+                                        client.query(&pathway_statement, &[
+                                            &feed.id,
+                                            &pathway.pathway_id,
+                                            &pathway.from_stop_id,
+                                            &pathway.to_stop_id,
+                                            &pathway.pathway_mode,
+                                            &(pathway.is_bidirectional == 1),
+                                            &pathway.length,
+                                            &pathway.traversal_time,
+                                            &pathway.stair_count,
+                                            &pathway.max_slope,
+                                            &pathway.min_width,
+                                            &pathway.signposted_as,
+                                            &pathway.reversed_signposted_as
+                                        ]).await.stage("station_navigation")?;
+                                    }
+
+                                    let transfer_statement = client.prepare(format!("INSERT INTO {schemaname}.transfers
+                                    (onestop_feed_id, from_stop_id, to_stop_id, from_route_id, to_route_id, from_trip_id, to_trip_id, transfer_type, min_transfer_time)
+                                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);").as_str()).await.stage("station_navigation")?;
+
+                                    for transfer in &transfers {
+                                        client.query(&transfer_statement, &[
+                                            &feed.id,
+                                            &transfer.from_stop_id,
+                                            &transfer.to_stop_id,
+                                            &transfer.from_route_id,
+                                            &transfer.to_route_id,
+                                            &transfer.from_trip_id,
+                                            &transfer.to_trip_id,
+                                            &transfer.transfer_type.unwrap_or(0),
+                                            &transfer.min_transfer_time
+                                        ]).await.stage("station_navigation")?;
+                                    }
+
+                                    fares::insert_fares(&client, schemaname, &feed.id, &file_path).await?;
+
+                                    let calendar_statement = client.prepare(format!("INSERT INTO {schemaname}.calendar
+                                    (onestop_feed_id, service_id, monday, tuesday, wednesday, thursday, friday, saturday, sunday, gtfs_start_date, gtfs_end_date)
+                                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) ON CONFLICT (onestop_feed_id, service_id) DO UPDATE SET
+                                    monday = $3, tuesday = $4, wednesday = $5, thursday = $6, friday = $7, saturday = $8, sunday = $9,
+                                    gtfs_start_date = $10, gtfs_end_date = $11;").as_str()).await.stage("calendar")?;
+
+                                    for (service_id, calendar) in &gtfs.calendar {
+                                        client.query(&calendar_statement, &[
+                                            &feed.id,
+                                            &service_id,
+                                            &calendar.monday,
+                                            &calendar.tuesday,
+                                            &calendar.wednesday,
+                                            &calendar.thursday,
+                                            &calendar.friday,
+                                            &calendar.saturday,
+                                            &calendar.sunday,
+                                            &calendar.start_date,
+                                            &calendar.end_date
+                                        ]).await.stage("calendar")?;
+                                    }
+
+                                    let calendar_dates_statement = client.prepare(format!("INSERT INTO {schemaname}.calendar_dates
+                                    (onestop_feed_id, service_id, gtfs_date, exception_type)
+                                    VALUES ($1, $2, $3, $4) ON CONFLICT (onestop_feed_id, service_id, gtfs_date) DO UPDATE SET
+                                    exception_type = $4;").as_str()).await.stage("calendar")?;
+
+                                    for (service_id, calendar_dates) in &gtfs.calendar_dates {
+                                        for calendar_date in calendar_dates {
+                                            client.query(&calendar_dates_statement, &[
+                                                &feed.id,
+                                                &service_id,
+                                                &calendar_date.date,
+                                                &service_calendar::exception_type_to_int(&calendar_date.exception_type)
+                                            ]).await.stage("calendar")?;
+                                        }
+                                    }
+
+                                    let service_days_statement = client.prepare(format!("INSERT INTO {schemaname}.service_days
+                                    (onestop_feed_id, service_id, active_dates)
+                                    VALUES ($1, $2, $3) ON CONFLICT (onestop_feed_id, service_id) DO UPDATE SET
+                                    active_dates = $3;").as_str()).await.stage("calendar")?;
+
+                                    let active_service_days = service_calendar::make_active_service_days(&gtfs);
+
+                                    let calendars_over_day_limit = service_calendar::calendars_over_day_limit(&gtfs);
+                                    if calendars_over_day_limit.len() > 0 {
+                                        feed_errors.push(IngestError::new("calendar", format!("{} services run for more than {} days, only their first {} days are in service_days: {}", calendars_over_day_limit.len(), service_calendar::MAX_CALENDAR_DAYS, service_calendar::MAX_CALENDAR_DAYS, calendars_over_day_limit.iter().take(20).join(", "))));
+                                    }
+
+                                    println!("{} services in the calendar", active_service_days.len());
+
+                                    for (service_id, active_dates) in &active_service_days {
+                                        client.query(&service_days_statement, &[
+                                            &feed.id,
+                                            &service_id,
+                                            &active_dates
+                                        ]).await.stage("calendar")?;
+                                    }
+
                                     println!("Uploading {} trips", gtfs.trips.len());
                                      
                                     let time = std::time::Instant::now();
                                     if skiptrips == false {
-                                        let trips: HashMap<(String, String), (&Trip, &PooledConnection<PostgresConnectionManager<NoTls>>)> = gtfs.trips.iter()
-                                        .map(|(key, trip)| ((key.to_owned(), feed.id.to_owned()), (trip, &client))).collect();
-                                        let trips_clone = trips.to_owned();
-                                        let trips_workers = trips_clone.into_iter().map( |((trip_id, feed_id), (trip, client))| async move {
-                                            let statement = client.prepare(format!("INSERT INTO {schemaname}.trips 
-                                            (onestop_feed_id, trip_id, service_id, route_id, trip_headsign, trip_short_name, shape_id, has_stop_headsign, stop_headsigns)
-                                             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (onestop_feed_id, trip_id) do update set
-                                             service_id = $3,
-                                             route_id = $4,
-                                                trip_headsign = $5,
-                                                trip_short_name = $6,
-                                                shape_id = $7,
-                                                has_stop_headsign = $8,
-                                                stop_headsigns = $9
-                                             ;").as_str()).await.unwrap();
-                                            let stoptimestatement = client.prepare(
-                                                format!("INSERT INTO {schemaname}.stoptimes 
-                                                (onestop_feed_id, trip_id, stop_id, stop_sequence, 
-                                                    arrival_time, departure_time, stop_headsign, point) 
-                                                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING;").as_str()).await.unwrap();
-                                            
+                                        let trips_writer = copy_upload::start_binary_copy(&client, schemaname, "trips",
+                                            &["onestop_feed_id", "trip_id", "service_id", "route_id", "trip_headsign", "trip_short_name", "shape_id", "has_stop_headsign", "stop_headsigns"]).await.stage("trips")?;
+                                        futures::pin_mut!(trips_writer);
+
+                                        for (trip_id, trip) in &gtfs.trips {
                                             let trip_headsign = titlecase_process_new(trip.trip_headsign.as_ref());
                                             //calculate if any stop time has a stop headsign
                                             let has_stop_headsign = trip.stop_times.iter().any(|stoptime| {
@@ -1237,22 +1936,65 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                             stop_headsigns_for_trip.dedup();
                                             let stop_headsigns_for_trip = stop_headsigns_for_trip;
 
-                                            client.query(
-                                                &statement,
+                                            trips_writer.as_mut().write(
                                         &[
-                                                    &feed_id,
+                                                    &feed.id,
                                                     &trip.id,
                                                     &trip.service_id,
                                                     &trip.route_id,
                                                     &trip_headsign,
                                                     &trip.trip_short_name,
-                                                    &trip.shape_id,
+                                                    &trip.shape_id.as_ref().filter(|shape_id| gtfs.shapes.contains_key(*shape_id)).or_else(|| trip_to_synthetic_shape.get(&trip.id).copied()),
                                                     &has_stop_headsign,
                                                     &stop_headsigns_for_trip
                                                 ],
-                                            ).await.unwrap();
+                                            ).await.stage("trips")?;
+                                        }
+
+                                        trips_writer.finish().await.stage("trips")?;
 
-                                            for stoptime in &trip.stop_times {
+                                        //stoptimes of these trips are only a template, departures are start_time + n * headway_secs until end_time
+                                        let frequency_statement = client.prepare(format!("INSERT INTO {schemaname}.frequencies
+                                        (onestop_feed_id, trip_id, start_time, end_time, headway_secs, exact_times)
+                                        VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING;").as_str()).await.stage("frequencies")?;
+
+                                        for (trip_id, trip) in &gtfs.trips {
+                                            for frequency in &trip.frequencies {
+                                                client.query(&frequency_statement, &[
+                                                    &feed.id,
+                                                    &trip.id,
+                                                    &(frequency.start_time as i32),
+                                                    &(frequency.end_time as i32),
+                                                    &(frequency.headway_secs as i32),
+                                                    &matches!(frequency.exact_times, Some(gtfs_structures::ExactTimes::ScheduleBased))
+                                                ]).await.stage("frequencies")?;
+                                            }
+                                        }
+
+                                        //only one COPY can run on a connection at a time, so stop times go after all the trips
+                                        let stoptimes_writer = copy_upload::start_binary_copy(&client, schemaname, "stoptimes",
+                                            &["onestop_feed_id", "trip_id", "stop_id", "stop_sequence", "arrival_time", "departure_time", "stop_headsign", "point", "shape_dist_traveled", "timepoint", "interpolated"]).await.stage("stoptimes")?;
+                                        futures::pin_mut!(stoptimes_writer);
+
+                                        let mut stoptimes_without_coordinates = 0;
+                                        let mut stoptimes_interpolated = 0;
+                                        let mut stoptimes_without_times = 0;
+
+                                        for (trip_id, trip) in &gtfs.trips {
+                                            //COPY has no ON CONFLICT, keep the first stop time of a repeated stop_sequence like the old insert did
+                                            let mut seen_stop_sequences: HashSet<i32> = HashSet::new();
+
+                                            //non-timepoint stops often have no times at all, fill them in from the timepoints around them
+                                            let resolved_times = stop_time_interpolation::interpolate_stop_times(
+                                                &trip.stop_times,
+                                                trip.shape_id.as_ref().and_then(|shape_id| gtfs.shapes.get(shape_id)),
+                                            );
+
+                                            for (stoptime, resolved_time) in trip.stop_times.iter().zip(resolved_times.iter()) {
+
+                                                if !seen_stop_sequences.insert(stoptime.stop_sequence as i32) {
+                                                    continue;
+                                                }
 
                                                 if stoptime.stop.latitude.is_some() && stoptime.stop.longitude.is_some() {
                                                     let point = ewkb::Point {
@@ -1264,28 +2006,51 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
                                                     let stop_headsign:Option<String> = titlecase_process_new(stoptime.stop_headsign.as_ref());
                                                 
-                                                    if stoptime.arrival_time.is_some() && stoptime.departure_time.is_some() {
-                                                        client
-                                                    .query(
-                                                        &stoptimestatement,
+                                                    if resolved_time.arrival_time.is_some() && resolved_time.departure_time.is_some() {
+                                                        if resolved_time.interpolated {
+                                                            stoptimes_interpolated = stoptimes_interpolated + 1;
+                                                        }
+
+                                                        stoptimes_writer
+                                                    .as_mut()
+                                                    .write(
                                                         &[
-                                                            &feed_id,
+                                                            &feed.id,
                                                             &trip.id,
                                                             &stoptime.stop.id,
                                                             &(stoptime.stop_sequence as i32),
-                                                            &toi64(&stoptime.arrival_time),
-                                                            &toi64(&stoptime.departure_time),
+                                                            &toi64(&resolved_time.arrival_time),
+                                                            &toi64(&resolved_time.departure_time),
                                                             &stop_headsign,
-                                                            &point
+                                                            &point,
+                                                            &stoptime.shape_dist_traveled.map(|distance| distance as f64),
+                                                            &(match stoptime.timepoint {
+                                                                gtfs_structures::TimepointType::Approximate => 0,
+                                                                gtfs_structures::TimepointType::Exact => 1,
+                                                            }),
+                                                            &resolved_time.interpolated
                                                         ],
-                                                    ).await.unwrap();
-                                                    }    
+                                                    ).await.stage("stoptimes")?;
+                                                    } else {
+                                                        stoptimes_without_times = stoptimes_without_times + 1;
+                                                    }
+                                                } else {
+                                                    stoptimes_without_coordinates = stoptimes_without_coordinates + 1;
                                                 }
                                             }
-                                        });
-                                        for worker in trips_workers {
-                                            let _ = tokio::join!(worker);
                                         }
+
+                                        stoptimes_writer.finish().await.stage("stoptimes")?;
+
+                                        if stoptimes_without_coordinates > 0 {
+                                            feed_errors.push(IngestError::new("stoptimes", format!("{} stop times skipped because their stop has no coordinates", stoptimes_without_coordinates)));
+                                        }
+
+                                        if stoptimes_without_times > 0 {
+                                            feed_errors.push(IngestError::new("stoptimes", format!("{} stop times skipped because they are before the first or after the last timed stop of their trip", stoptimes_without_times)));
+                                        }
+
+                                        println!("{} stop times interpolated for {}", stoptimes_interpolated, feed.id);
                                                       
                                     println!("{} with {} trips took {}ms", feed.id, gtfs.trips.len(), time.elapsed().as_millis());
                                     }
@@ -1350,16 +2115,22 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                         }
                                     }
 
-                                    let stopstatement = client.prepare(format!(
-                                        "INSERT INTO {schemaname}.stops
-                                     (onestop_feed_id, gtfs_id, name, displayname, code, gtfs_desc, point, route_types, routes, location_type, parent_station, children_ids, children_route_types, hidden, location_alias)
-                                           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) ON CONFLICT DO NOTHING;"
-                                    ).as_str()).await.unwrap();
+                                    let stops_writer = copy_upload::start_binary_copy(&client, schemaname, "stops",
+                                        &["onestop_feed_id", "gtfs_id", "name", "displayname", "code", "gtfs_desc", "point", "route_types", "routes", "location_type", "parent_station", "children_ids", "children_route_types", "hidden", "location_alias", "level_id", "platform_code", "zone_id", "timezone"]).await.stage("stops")?;
+                                    futures::pin_mut!(stops_writer);
+                                    let stops_without_coordinates = gtfs.stops.values()
+                                        .filter(|stop| station_navigation::stop_or_parent_coordinates(&gtfs, stop).is_none())
+                                        .map(|stop| stop.id.to_owned())
+                                        .collect::<Vec<String>>();
+                                    if stops_without_coordinates.len() > 0 {
+                                        feed_errors.push(IngestError::new("stops", format!("{} stops have no coordinates and were skipped: {}", stops_without_coordinates.len(), stops_without_coordinates.iter().take(20).join(", "))));
+                                    }
                                     for (stop_id, stop) in &gtfs.stops {
-                                       if stop.latitude.is_some() && stop.longitude.is_some() {
+                                       //generic nodes and boarding areas sit inside their parent, use its position so pathways can reach them
+                                       if let Some((stop_lon, stop_lat)) = station_navigation::stop_or_parent_coordinates(&gtfs, stop) {
                                         let point = ewkb::Point {
-                                            x: stop.longitude.unwrap(),
-                                            y: stop.latitude.unwrap(),
+                                            x: stop_lon,
+                                            y: stop_lat,
                                             srid: Some(4326),
                                         };
                                         let name = titlecase_process_new_nooption(&stop.name);
@@ -1367,17 +2138,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
                                         let fetch_of_dedup = hashmap_stops_dedup_meta.get(&stop.id);
 
+                                        let timezone = feed_timezone::stop_timezone(&gtfs, stop, default_timezone.as_ref())
+                                            .map(|timezone| timezone.name().to_string());
+
+                                        //generic nodes and boarding areas are only there for pathways, they don't belong in stop lists or search
                                         let hidden_stop = match fetch_of_dedup {
                                             Some(fetch_of_dedup) => fetch_of_dedup.0,
                                             _ => false
-                                        };
+                                        } || matches!(stop.location_type, LocationType::GenericNode | LocationType::BoardingArea);
 
                                         let alias_names: Option<&Vec<String>> = match fetch_of_dedup {
                                             Some(fetch_of_dedup) => Some(fetch_of_dedup.1.deref()),
                                             _ => None
                                         };
 
-                                        client.query(&stopstatement, &[
+                                        stops_writer.as_mut().write(&[
                                             &feed.id,
                                             &stop.id,
                                             &name,
@@ -1392,11 +2167,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                             &stop_id_to_children_ids.get(&stop.id),
                                             &stop_ids_to_children_route_types.get(&stop.id),
                                             &hidden_stop,
-                                            &alias_names
-                                        ]).await.unwrap();
+                                            &alias_names,
+                                            &stop.level_id,
+                                            &stop.platform_code,
+                                            &stop.zone_id,
+                                            &timezone
+                                        ]).await.stage("stops")?;
                                        }
                                     }
 
+                                    stops_writer.finish().await.stage("stops")?;
+
                                     let start_hull_time = chrono::prelude::Utc::now().timestamp_nanos_opt().unwrap();
                                     //convex hull calcs
                                     let mut shape_points = gtfs.shapes.iter().map(|(a,b)| b)
@@ -1405,7 +2186,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                     .collect::<Vec<(f64, f64)>>();
                                     shape_points.par_sort_unstable_by(|a, b| match a.0.partial_cmp(&b.0) {
                                         Some(ord) => ord,
-                                        None => a.1.partial_cmp(&b.1).unwrap(),
+                                        None => a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal),
                                     });
                                     shape_points.dedup();
                                     let shape_points = shape_points;
@@ -1421,19 +2202,22 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                         .to_postgis_wgs84();
                                     
                                     let hull_postgres = match num_of_points_polygon {
-                                        0 => geo::Polygon::new(
+                                        0 => match (most_lon, most_lat, least_lon, least_lat) {
+                                            (Some(most_lon), Some(most_lat), Some(least_lon), Some(least_lat)) => geo::Polygon::new(
                                             geo::LineString::from(vec![
-                                                (most_lon.unwrap(), most_lat.unwrap()),
-                                             (most_lon.unwrap(), least_lat.unwrap()),
-                                             (least_lon.unwrap(), least_lat.unwrap()), (least_lon.unwrap(), most_lat.unwrap()), (most_lon.unwrap(), most_lat.unwrap())]),vec![]).to_postgis_wgs84(),
+                                                (most_lon, most_lat),
+                                             (most_lon, least_lat),
+                                             (least_lon, least_lat), (least_lon, most_lat), (most_lon, most_lat)]),vec![]).to_postgis_wgs84(),
+                                            _ => return Err(IngestError::new("hull", String::from("no shapes and no stop coordinates to make a hull from"))),
+                                        },
                                         _ => hull_postgres
                                     };
 
                                     if gtfs.routes.len() > 0 as usize {
                                         let _ = client.query(
-                                            format!("INSERT INTO {schemaname}.static_feeds (onestop_feed_id, max_lat, max_lon, min_lat, min_lon, operators, operators_to_gtfs_ids, hull)
+                                            format!("INSERT INTO {schemaname}.static_feeds (onestop_feed_id, max_lat, max_lon, min_lat, min_lon, operators, operators_to_gtfs_ids, hull, default_timezone)
                                         
-                                            VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (onestop_feed_id) do update set operators = $6, operators_to_gtfs_ids = $7, hull = $8;").as_str(), &[
+                                            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (onestop_feed_id) do update set operators = $6, operators_to_gtfs_ids = $7, hull = $8, default_timezone = $9;").as_str(), &[
                                         &feed.id,
                                         &most_lat,
                                         &most_lon,
@@ -1441,8 +2225,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                         &least_lon,
                                         &operator_pairs_hashmap.par_iter().map(|(a,b)| a).collect::<Vec<&String>>(),
                                         &operator_pairs_hashmap,
-                                        &hull_postgres
-                                    ]).await.unwrap();
+                                        &hull_postgres,
+                                        &default_timezone.map(|timezone| timezone.name().to_string())
+                                    ]).await.stage("static_feeds")?;
                                     if skiptrips == false {
                                         
                                         //get current unix timestamp
@@ -1452,46 +2237,73 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                         let in_ms = since_the_epoch.as_millis();
                                         client.execute(
                                             format!(
-                                                "INSERT INTO {schemaname}.feeds_updated (onestop_feed_id, created_trips, updated_trips_time_ms) VALUES ($1, $2, $3) ON CONFLICT (onestop_feed_id) DO UPDATE SET created_trips = $2, updated_trips_time_ms = $3;"
+                                                "INSERT INTO {schemaname}.feeds_updated (onestop_feed_id, created_trips, updated_trips_time_ms, content_hash) VALUES ($1, $2, $3, $4) ON CONFLICT (onestop_feed_id) DO UPDATE SET created_trips = $2, updated_trips_time_ms = $3, content_hash = $4;"
                                             ).as_str()
                                             , &[
                                             &feed.id,
                                             &true,
-                                            &(in_ms as i64)
-                                        ]).await.unwrap();
+                                            &(in_ms as i64),
+                                            &content_hash
+                                        ]).await.stage("feeds_updated")?;
+                                    }
+                                    }
 
-                                        client.execute(
-                                            format!(
-                                                "DELETE FROM {schemaname}.gtfs_errors WHERE onestop_feed_id = $1;"
-                                            ).as_str()
-                                            , &[
-                                            &feed.id,
-                                        ]).await.unwrap();
+                                    client.commit().await.stage("commit")?;
+                                    Ok::<(), IngestError>(())
+                                    }.await;
+
+                                    //the transaction rolled back on its own if anything failed, the feed keeps its previous data
+                                    match ingest_result {
+                                        Ok(()) => {
+                                            println!("Committed {}", &feed.id);
+                                            imported_feeds.lock().unwrap().insert(key.to_owned());
+                                        },
+                                        Err(err) => {
+                                            println!("{} failed at {}: {}", &feed.id, err.stage, err.message);
+                                            feed_errors.push(err);
+                                        }
                                     }
+
+                                    if let Err(err) = ingest_errors::write_feed_errors(&client, schemaname, &feed.id, &feed_errors).await {
+                                        println!("Could not record errors for {}: {}", &feed.id, err);
                                     }
                                 },
                                 Err(gtfs_err) => {
                                     println!("{} is not a valid gtfs feed", &key);
                                     println!("{:?}", gtfs_err);
-                                    //we should save this in some database
                                     let errormsg = format!("{:#?}", gtfs_err);
-                                    client.query(format!("INSERT INTO {schemaname}.gtfs_errors (onestop_feed_id, error) VALUES ($1, $2) ON CONFLICT (onestop_feed_id) DO UPDATE SET error = $2;").as_str(), &[
-                                        &feed.id,
-                                        &errormsg
-                                    ]).await.unwrap();
+                                    if let Err(err) = ingest_errors::write_feed_errors(&client, schemaname, &feed.id, &[IngestError::new("read", errormsg)]).await {
+                                        println!("Could not record errors for {}: {}", &feed.id, err);
+                                    }
                                 }
                             }
                             }
                         }
                     },
                     dmfr::FeedSpec::GtfsRt => {
-                            client.query(format!("INSERT INTO {schemaname}.realtime_feeds (onestop_feed_id, name, operators, operators_to_gtfs_ids)
-                            VALUES ($1, $2, $3, $4) ON CONFLICT do nothing;").as_str(), &[
+                            let realtime_result = client.query(format!("INSERT INTO {schemaname}.realtime_feeds (onestop_feed_id, name, operators, operators_to_gtfs_ids,
+                            realtime_vehicle_positions, realtime_trip_updates, realtime_alerts, authorization_type, authorization_param_name)
+                            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (onestop_feed_id) do update set
+                            realtime_vehicle_positions = $5,
+                            realtime_trip_updates = $6,
+                            realtime_alerts = $7,
+                            authorization_type = $8,
+                            authorization_param_name = $9;").as_str(), &[
                             &feed.id,
                             &feed.name,
                             &operator_pairs_hashmap.iter().map(|(a,b)| a).collect::<Vec<&String>>(),
-                            &operator_pairs_hashmap
-                        ]).await.unwrap();
+                            &operator_pairs_hashmap,
+                            &feed.urls.realtime_vehicle_positions.as_ref().map(|url| url.to_string()),
+                            &feed.urls.realtime_trip_updates.as_ref().map(|url| url.to_string()),
+                            &feed.urls.realtime_alerts.as_ref().map(|url| url.to_string()),
+                            &feed.authorization.as_ref().map(|authorization| authorization.type_.to_string()),
+                            &feed.authorization.as_ref().and_then(|authorization| authorization.param_name.to_owned())
+                        ]).await;
+
+                        if let Err(err) = realtime_result {
+                            println!("Could not save realtime feed {}: {}", &feed.id, err);
+                            let _ = ingest_errors::write_feed_errors(&client, schemaname, &feed.id, &[IngestError::new("realtime_feeds", format!("{}", err))]).await;
+                        }
                     },
                     _ => {
                         //do nothing
@@ -1505,6 +2317,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
     futures::future::join_all(handles).await;
     println!("Done ingesting all gtfs statics");
+
+    //a feed that failed stays in changed, so the next --onlychangedfeeds run retries it
+    if let Some(mut manifest) = feed_manifest::read_manifest() {
+        let imported_feeds = imported_feeds.lock().unwrap();
+        let (imported, waiting): (Vec<String>, Vec<String>) = manifest
+            .changed
+            .into_iter()
+            .partition(|feed_id| imported_feeds.contains(feed_id));
+        manifest.changed = waiting;
+        manifest.unchanged.extend(imported);
+
+        if let Err(err) = feed_manifest::write_manifest(&manifest) {
+            println!("Could not update {}: {}", feed_manifest::MANIFEST_PATH, err);
+        }
+    }
     println!("number of operators: {}", operatorhashmap.len());
     for (operator_id, operator) in operatorhashmap {
         //println!("{:?}", operator);
@@ -57,6 +57,40 @@ pub async fn make_prod_index(client: &tokio_postgres::Client, schemaname: &Strin
         .await
         .unwrap();
 
+    println!("stop times index by stop for departure boards");
+
+    client
+        .batch_execute(
+            format!(
+                "
+        CREATE INDEX IF NOT EXISTS gtfs_static_stoptimes_by_stop ON {schemaname}.stoptimes (onestop_feed_id, stop_id);
+    "
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+
+    println!("trigram and full text indexes for search");
+
+    client
+        .batch_execute(
+            format!(
+                "
+        CREATE EXTENSION IF NOT EXISTS pg_trgm;
+        CREATE INDEX IF NOT EXISTS gtfs_static_stops_name_trgm ON {schemaname}.stops USING GIN (name gin_trgm_ops);
+        CREATE INDEX IF NOT EXISTS gtfs_static_stops_displayname_trgm ON {schemaname}.stops USING GIN (displayname gin_trgm_ops);
+        CREATE INDEX IF NOT EXISTS gtfs_static_stops_name_tsvector ON {schemaname}.stops USING GIN (to_tsvector('simple', name));
+        CREATE INDEX IF NOT EXISTS gtfs_static_routes_short_name_trgm ON {schemaname}.routes USING GIN (short_name gin_trgm_ops);
+        CREATE INDEX IF NOT EXISTS gtfs_static_routes_long_name_trgm ON {schemaname}.routes USING GIN (long_name gin_trgm_ops);
+        CREATE INDEX IF NOT EXISTS gtfs_static_operators_name_trgm ON {schemaname}.operators USING GIN (name gin_trgm_ops);
+    "
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+
     println!("make static hulls...");
 
     client
@@ -1,83 +1,56 @@
+use rayon::prelude::*;
 use std::fs;
-use std::process::Command;
+use std::path::Path;
+
+mod extract_feed;
 
 fn main() {
-    // Set the source and destination directories
     let source_dir = "gtfs_static_zips";
     let destination_dir = "gtfs_uncompressed";
 
-    // Change to the source directory
-    std::env::set_current_dir(&source_dir).expect("Failed to change to the source directory");
-
-    // Loop through each ZIP file in the source directory
-    for entry in fs::read_dir(".").expect("Failed to read source directory") {
-        if let Ok(entry) = entry {
-            if let Some(zip_file) = entry.file_name().to_str() {
-                if zip_file.ends_with(".zip") {
-                    // Create a folder with the same name as the ZIP file (without the .zip extension) in the destination directory
-                    let folder_name = &zip_file[..zip_file.len() - 4];
-                    let destination_folder = format!("{}/{}", destination_dir, folder_name);
-
-                    // Check if the destination folder already exists, if not, create it
-                    if let Err(e) = fs::create_dir_all(&destination_folder) {
-                        eprintln!("Failed to create destination folder: {}", e);
-                        continue;
+    fs::create_dir_all(destination_dir).expect("Failed to create destination directory");
+
+    let zip_files = fs::read_dir(source_dir)
+        .expect("Failed to read source directory")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map(|ext| ext == "zip").unwrap_or(false))
+        .collect::<Vec<_>>();
+
+    println!("Extracting {} zip files", zip_files.len());
+
+    let mut errors = zip_files
+        .par_iter()
+        .filter_map(|zip_path| {
+            let feed_id = zip_path.file_stem()?.to_string_lossy().to_string();
+            let destination_folder = Path::new(destination_dir).join(&feed_id);
+
+            match extract_feed::extract_feed(zip_path, &destination_folder) {
+                Ok(extracted) => {
+                    match extracted.gtfs_root.as_os_str().is_empty() {
+                        true => println!("Unzipped {}, {} files", feed_id, extracted.files_written),
+                        false => println!(
+                            "Unzipped {}, {} files from {}",
+                            feed_id,
+                            extracted.files_written,
+                            extracted.gtfs_root.display()
+                        ),
                     }
-
-                    // Unzip the file into the destination folder
-                    let unzip_result = Command::new("unzip")
-                        .arg("-o")
-                        .arg(zip_file)
-                        .arg("-d")
-                        .arg(&destination_folder)
-                        .status();
-
-                    if unzip_result.is_err() {
-                        eprintln!(
-                            "Failed to unzip {}: {}",
-                            zip_file,
-                            unzip_result.unwrap_err()
-                        );
-                        continue;
-                    }
-                    println!("Unzipped {}", zip_file);
+                    None
                 }
+                Err(err) => Some((feed_id, err)),
             }
-        }
-    }
+        })
+        .collect::<Vec<(String, String)>>();
 
     println!("Unzipping complete!");
 
-    // Flatten each feed
-    let flatten_result = Command::new("cargo")
-        .arg("run")
-        .arg("--bin")
-        .arg("flattenuncompressed")
-        .status();
-
-    if let Err(e) = flatten_result {
-        eprintln!("Failed to run flattenuncompressed: {}", e);
-    }
-    // Change the permissions
-    let chmod_result = Command::new("chmod")
-        .arg("-R")
-        .arg("+r")
-        .arg(destination_dir)
-        .status();
-    let rm_result = Command::new("find")
-        .arg(destination_dir)
-        .arg("-type")
-        .arg("d")
-        .arg("-name")
-        .arg("__MACOSX")
-        .arg("-prune")
-        .status();
-
-    if let Err(e) = rm_result {
-        eprintln!("Failed to change permissions: {}", e);
-    }
+    if errors.len() > 0 {
+        errors.sort();
 
-    if let Err(e) = chmod_result {
-        eprintln!("Failed to change permissions: {}", e);
+        println!("{} feeds could not be extracted:", errors.len());
+        for (feed_id, err) in errors.iter() {
+            println!("{}: {}", feed_id, err);
+        }
     }
 }
@@ -3,7 +3,7 @@ use serde_json::Error as SerdeError;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fs;
-mod dmfr;
+use aspenlib::dmfr;
 use bb8_postgres::PostgresConnectionManager;
 use clap::Parser;
 use futures;
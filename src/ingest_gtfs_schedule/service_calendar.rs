@@ -0,0 +1,93 @@
+use chrono::Datelike;
+use chrono::NaiveDate;
+use chrono::Weekday;
+use gtfs_structures::Calendar;
+use gtfs_structures::Exception;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+//some feeds ship decade long calendars, don't let one of them eat the ram
+pub const MAX_CALENDAR_DAYS: i64 = 3660;
+
+pub fn exception_type_to_int(input: &Exception) -> i16 {
+    match input {
+        Exception::Added => 1,
+        Exception::Deleted => 2,
+    }
+}
+
+pub fn calendar_runs_on_weekday(calendar: &Calendar, weekday: Weekday) -> bool {
+    match weekday {
+        Weekday::Mon => calendar.monday,
+        Weekday::Tue => calendar.tuesday,
+        Weekday::Wed => calendar.wednesday,
+        Weekday::Thu => calendar.thursday,
+        Weekday::Fri => calendar.friday,
+        Weekday::Sat => calendar.saturday,
+        Weekday::Sun => calendar.sunday,
+    }
+}
+
+//service ids whose calendar.txt range is longer than MAX_CALENDAR_DAYS, only its first days are expanded
+pub fn calendars_over_day_limit(gtfs: &gtfs_structures::Gtfs) -> Vec<String> {
+    let mut service_ids = gtfs
+        .calendar
+        .iter()
+        .filter(|(_, calendar)| {
+            (calendar.end_date - calendar.start_date).num_days() >= MAX_CALENDAR_DAYS
+        })
+        .map(|(service_id, _)| service_id.to_owned())
+        .collect::<Vec<String>>();
+
+    service_ids.sort();
+    service_ids
+}
+
+//returns service_id -> every date the service is active on
+//calendar.txt gives the weekly pattern between start_date and end_date, calendar_dates.txt then adds or removes single days
+//feeds that only use calendar_dates.txt (very common in Europe) are handled by the exceptions alone
+pub fn make_active_service_days(gtfs: &gtfs_structures::Gtfs) -> HashMap<String, Vec<NaiveDate>> {
+    let mut active_days: HashMap<String, BTreeSet<NaiveDate>> = HashMap::new();
+
+    for (service_id, calendar) in &gtfs.calendar {
+        let days = active_days
+            .entry(service_id.to_owned())
+            .or_insert(BTreeSet::new());
+
+        let mut date = calendar.start_date;
+        let mut count_of_days = 0;
+        while date <= calendar.end_date && count_of_days < MAX_CALENDAR_DAYS {
+            if calendar_runs_on_weekday(calendar, date.weekday()) {
+                days.insert(date);
+            }
+
+            date = match date.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+            count_of_days = count_of_days + 1;
+        }
+    }
+
+    for (service_id, calendar_dates) in &gtfs.calendar_dates {
+        let days = active_days
+            .entry(service_id.to_owned())
+            .or_insert(BTreeSet::new());
+
+        for calendar_date in calendar_dates {
+            match calendar_date.exception_type {
+                Exception::Added => {
+                    days.insert(calendar_date.date);
+                }
+                Exception::Deleted => {
+                    days.remove(&calendar_date.date);
+                }
+            }
+        }
+    }
+
+    active_days
+        .into_iter()
+        .map(|(service_id, days)| (service_id, days.into_iter().collect::<Vec<NaiveDate>>()))
+        .collect()
+}
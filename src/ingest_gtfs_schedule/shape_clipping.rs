@@ -0,0 +1,304 @@
+use geo::{BooleanOps, ClosestPoint, Contains, LineLocatePoint};
+use serde::Deserialize;
+use std::fs;
+
+pub const SHAPE_CLIPPING_PATH: &str = "shape_clipping.json";
+
+const SUPPORTED_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+struct RawShapeClipping {
+    version: u32,
+    rules: Vec<RawClipRule>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeepSide {
+    Before,
+    After,
+}
+
+//unknown keys are ignored, each rule can carry a "note" saying what it removes
+#[derive(Debug, Deserialize)]
+struct RawClipRule {
+    feed_id: String,
+    route_id: Option<String>,
+    shape_id: Option<String>,
+    color: Option<String>,
+    //[lon, lat] ring, points of the shape inside it are removed
+    polygon: Option<Vec<[f64; 2]>>,
+    //[lon, lat], the shape is cut at the closest point on it
+    cut_at: Option<[f64; 2]>,
+    keep: Option<KeepSide>,
+}
+
+pub enum ClipAction {
+    RemoveInside(geo::Polygon),
+    CutAt(geo::Point, KeepSide),
+}
+
+//route_id, shape_id and color select which shapes of the feed the rule applies to, every one that is set has to match
+pub struct ClipRule {
+    pub feed_id: String,
+    pub route_id: Option<String>,
+    pub shape_id: Option<String>,
+    //hex colour of the route after colour overrides, eg "eb131b"
+    pub color: Option<String>,
+    pub action: ClipAction,
+}
+
+#[derive(Default)]
+pub struct ShapeClipping {
+    pub rules: Vec<ClipRule>,
+}
+
+fn parse_rule(raw: RawClipRule) -> Result<ClipRule, String> {
+    let action = match (raw.polygon, raw.cut_at) {
+        (Some(ring), None) => {
+            if ring.len() < 3 {
+                return Err(String::from("polygon needs at least 3 points"));
+            }
+
+            ClipAction::RemoveInside(geo::Polygon::new(
+                geo::LineString::from(
+                    ring.iter()
+                        .map(|[lon, lat]| (*lon, *lat))
+                        .collect::<Vec<(f64, f64)>>(),
+                ),
+                vec![],
+            ))
+        }
+        (None, Some([lon, lat])) => match raw.keep {
+            Some(keep) => ClipAction::CutAt(geo::Point::new(lon, lat), keep),
+            None => return Err(String::from("cut_at needs keep: before or after")),
+        },
+        _ => return Err(String::from("needs exactly one of polygon or cut_at")),
+    };
+
+    Ok(ClipRule {
+        feed_id: raw.feed_id,
+        route_id: raw.route_id,
+        shape_id: raw.shape_id,
+        color: raw
+            .color
+            .map(|color| color.trim_start_matches('#').to_lowercase()),
+        action: action,
+    })
+}
+
+pub fn read_shape_clipping(path: &str) -> Result<ShapeClipping, String> {
+    let file = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let raw: RawShapeClipping =
+        serde_json::from_str(&file).map_err(|err| format!("{}: {}", path, err))?;
+
+    if raw.version != SUPPORTED_VERSION {
+        return Err(format!(
+            "{} is version {}, this importer reads version {}",
+            path, raw.version, SUPPORTED_VERSION
+        ));
+    }
+
+    let rules = raw
+        .rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, raw)| {
+            parse_rule(raw).map_err(|err| format!("{} rule {}: {}", path, index, err))
+        })
+        .collect::<Result<Vec<ClipRule>, String>>()?;
+
+    Ok(ShapeClipping { rules: rules })
+}
+
+impl ClipRule {
+    pub fn matches(
+        &self,
+        feed_id: &str,
+        shape_id: &str,
+        route_ids: &[String],
+        color: &str,
+    ) -> bool {
+        self.feed_id == feed_id
+            && self.shape_id.as_deref().map_or(true, |id| id == shape_id)
+            && self
+                .route_id
+                .as_ref()
+                .map_or(true, |id| route_ids.contains(id))
+            && self
+                .color
+                .as_deref()
+                .map_or(true, |rule_color| rule_color == color)
+    }
+}
+
+//the parts of the line outside the polygon, joined back together in the order they appear along the line
+fn remove_inside(polygon: &geo::Polygon, line: &geo::LineString) -> geo::LineString {
+    let outside = polygon.clip(&geo::MultiLineString::new(vec![line.clone()]), true);
+
+    let mut pieces = outside.0;
+    pieces.sort_by(|a, b| {
+        let position = |piece: &geo::LineString| {
+            piece
+                .0
+                .first()
+                .and_then(|coord| line.line_locate_point(&geo::Point::from(*coord)))
+                .unwrap_or(0.)
+        };
+        position(a).total_cmp(&position(b))
+    });
+
+    let mut coords: Vec<geo::Coord> = vec![];
+    for coord in pieces.into_iter().flat_map(|piece| piece.0) {
+        if coords.last() != Some(&coord) {
+            coords.push(coord);
+        }
+    }
+
+    geo::LineString::new(coords)
+}
+
+//splits the line at the point on it closest to cut, and keeps one side
+fn cut_at(cut: &geo::Point, keep: KeepSide, line: &geo::LineString) -> geo::LineString {
+    let closest_segment = line
+        .lines()
+        .enumerate()
+        .filter_map(|(index, segment)| match segment.closest_point(cut) {
+            geo::Closest::Intersection(point) | geo::Closest::SinglePoint(point) => {
+                let distance = (point.x() - cut.x()).hypot(point.y() - cut.y());
+                Some((index, point, distance))
+            }
+            geo::Closest::Indeterminate => None,
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2));
+
+    let (index, point) = match closest_segment {
+        Some((index, point, _)) => (index, point.0),
+        None => return line.clone(),
+    };
+
+    //segment index lies between coordinate index and index + 1
+    let coords = match keep {
+        KeepSide::Before => line.0[..=index]
+            .iter()
+            .copied()
+            .chain(std::iter::once(point))
+            .collect::<Vec<geo::Coord>>(),
+        KeepSide::After => std::iter::once(point)
+            .chain(line.0[(index + 1)..].iter().copied())
+            .collect::<Vec<geo::Coord>>(),
+    };
+
+    let mut deduped: Vec<geo::Coord> = vec![];
+    for coord in coords {
+        if deduped.last() != Some(&coord) {
+            deduped.push(coord);
+        }
+    }
+
+    geo::LineString::new(deduped)
+}
+
+impl ShapeClipping {
+    //returns the clipped line and (rule index, points removed) for every rule that matched
+    pub fn apply(
+        &self,
+        feed_id: &str,
+        shape_id: &str,
+        route_ids: &[String],
+        color: &str,
+        line: geo::LineString,
+    ) -> (geo::LineString, Vec<(usize, usize)>) {
+        let mut line = line;
+        let mut removed_per_rule = vec![];
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.matches(feed_id, shape_id, route_ids, color) {
+                continue;
+            }
+
+            let before = line.0.len();
+
+            line = match &rule.action {
+                ClipAction::RemoveInside(polygon) => {
+                    let removed = line
+                        .points()
+                        .filter(|point| polygon.contains(point))
+                        .count();
+                    removed_per_rule.push((index, removed));
+                    if removed == 0 {
+                        continue;
+                    }
+                    remove_inside(polygon, &line)
+                }
+                ClipAction::CutAt(cut, keep) => {
+                    let clipped = cut_at(cut, *keep, &line);
+                    //the cut adds one point where the line was split
+                    removed_per_rule.push((index, (before + 1).saturating_sub(clipped.0.len())));
+                    clipped
+                }
+            };
+        }
+
+        (line, removed_per_rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(coords: &[(f64, f64)]) -> geo::LineString {
+        geo::LineString::from(coords.to_vec())
+    }
+
+    #[test]
+    fn cut_inside_the_line_splits_it() {
+        let shape = line(&[(0., 0.), (1., 0.), (2., 0.)]);
+        let cut = geo::Point::new(1.5, 1.);
+
+        assert_eq!(
+            cut_at(&cut, KeepSide::Before, &shape),
+            line(&[(0., 0.), (1., 0.), (1.5, 0.)])
+        );
+        assert_eq!(
+            cut_at(&cut, KeepSide::After, &shape),
+            line(&[(1.5, 0.), (2., 0.)])
+        );
+    }
+
+    #[test]
+    fn cut_beyond_the_end_of_the_line() {
+        let shape = line(&[(0., 0.), (1., 0.), (2., 0.)]);
+        let cut = geo::Point::new(5., 0.);
+
+        //the cut lands on the last point, before is the whole line without a repeated end
+        assert_eq!(cut_at(&cut, KeepSide::Before, &shape), shape);
+        assert_eq!(cut_at(&cut, KeepSide::After, &shape), line(&[(2., 0.)]));
+    }
+
+    #[test]
+    fn remove_inside_joins_the_pieces_in_order() {
+        let shape = line(&[(0., 0.), (3., 0.), (5., 0.), (7., 0.), (10., 0.)]);
+        let polygon = geo::Polygon::new(
+            line(&[(4., -1.), (6., -1.), (6., 1.), (4., 1.), (4., -1.)]),
+            vec![],
+        );
+
+        assert_eq!(
+            remove_inside(&polygon, &shape),
+            line(&[(0., 0.), (3., 0.), (4., 0.), (6., 0.), (7., 0.), (10., 0.)])
+        );
+    }
+
+    #[test]
+    fn remove_inside_keeps_a_line_outside_the_polygon() {
+        let shape = line(&[(0., 5.), (10., 5.)]);
+        let polygon = geo::Polygon::new(
+            line(&[(4., -1.), (6., -1.), (6., 1.), (4., 1.), (4., -1.)]),
+            vec![],
+        );
+
+        assert_eq!(remove_inside(&polygon, &shape), shape);
+    }
+}
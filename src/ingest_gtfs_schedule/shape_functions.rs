@@ -12,7 +12,7 @@ pub async fn render_vector_tile_functions(client: &tokio_postgres::Client, schem
             ST_Transform(linestring, 3857),
             ST_TileEnvelope(z, x, y),
             4096, 64, true) AS geom,
-            onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color
+            onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color, dark_color, synthetic
         FROM gtfs.shapes
         WHERE (linestring && ST_Transform(ST_TileEnvelope(z, x, y), 4326)) AND (route_type = 3 OR route_type = 11 OR route_type = 200)
     ) as tile WHERE geom IS NOT NULL;
@@ -35,7 +35,7 @@ pub async fn render_vector_tile_functions(client: &tokio_postgres::Client, schem
         ST_Transform(linestring, 3857),
         ST_TileEnvelope(z, x, y),
         4096, 64, true) AS geom,
-        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color
+        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color, dark_color, synthetic
     FROM gtfs.shapes
     WHERE (linestring && ST_Transform(ST_TileEnvelope(z, x, y), 4326)) AND route_type != 3 AND route_type != 11
     ) as tile WHERE geom IS NOT NULL;
@@ -58,7 +58,7 @@ pub async fn render_vector_tile_functions(client: &tokio_postgres::Client, schem
         ST_Transform(linestring, 3857),
         ST_TileEnvelope(z, x, y),
         4096, 64, true) AS geom,
-        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color
+        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color, dark_color, synthetic
     FROM gtfs.shapes
     WHERE (linestring && ST_Transform(ST_TileEnvelope(z, x, y), 4326)) AND (route_type = 0 OR route_type = 1 OR route_type = 5 OR route_type = 12)
     ) as tile WHERE geom IS NOT NULL;
@@ -84,7 +84,7 @@ pub async fn render_vector_tile_functions(client: &tokio_postgres::Client, schem
         ST_Transform(linestring, 3857),
         ST_TileEnvelope(z, x, y),
         4096, 64, true) AS geom,
-        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color
+        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color, dark_color, synthetic
     FROM gtfs.shapes
     WHERE (linestring && ST_Transform(ST_TileEnvelope(z, x, y), 4326)) AND (route_type = 2)
     ) as tile WHERE geom IS NOT NULL;
@@ -112,7 +112,7 @@ pub async fn render_vector_tile_functions(client: &tokio_postgres::Client, schem
         ST_Transform(linestring, 3857),
         ST_TileEnvelope(z, x, y),
         4096, 64, true) AS geom,
-        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color
+        onestop_feed_id, shape_id, color, routes, route_type, route_label, text_color, dark_color, synthetic
     FROM gtfs.shapes
     WHERE (linestring && ST_Transform(ST_TileEnvelope(z, x, y), 4326)) AND (route_type = 4 OR route_type = 6 OR route_type = 7)
     ) as tile WHERE geom IS NOT NULL;
@@ -0,0 +1,58 @@
+use serde::Deserialize;
+
+//gtfs_structures doesn't keep levels.txt and only partially keeps pathways and transfers, so these are read straight from the csv
+
+#[derive(Debug, Deserialize)]
+pub struct RawLevel {
+    pub level_id: String,
+    pub level_index: f64,
+    pub level_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawPathway {
+    pub pathway_id: String,
+    pub from_stop_id: String,
+    pub to_stop_id: String,
+    //1 walkway, 2 stairs, 3 moving sidewalk, 4 escalator, 5 elevator, 6 fare gate, 7 exit gate
+    pub pathway_mode: i16,
+    pub is_bidirectional: i16,
+    pub length: Option<f64>,
+    pub traversal_time: Option<i32>,
+    pub stair_count: Option<i32>,
+    pub max_slope: Option<f64>,
+    pub min_width: Option<f64>,
+    pub signposted_as: Option<String>,
+    pub reversed_signposted_as: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawTransfer {
+    pub from_stop_id: Option<String>,
+    pub to_stop_id: Option<String>,
+    pub from_route_id: Option<String>,
+    pub to_route_id: Option<String>,
+    pub from_trip_id: Option<String>,
+    pub to_trip_id: Option<String>,
+    pub transfer_type: Option<i16>,
+    pub min_transfer_time: Option<i32>,
+}
+
+//(lon, lat) of the stop, or of the closest ancestor that has one
+pub fn stop_or_parent_coordinates(
+    gtfs: &gtfs_structures::Gtfs,
+    stop: &gtfs_structures::Stop,
+) -> Option<(f64, f64)> {
+    let mut current = stop;
+
+    //boarding area -> platform -> station is as deep as gtfs goes
+    for _ in 0..3 {
+        if let (Some(lon), Some(lat)) = (current.longitude, current.latitude) {
+            return Some((lon, lat));
+        }
+
+        current = gtfs.stops.get(current.parent_station.as_ref()?)?.as_ref();
+    }
+
+    None
+}
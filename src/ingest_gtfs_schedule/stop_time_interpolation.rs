@@ -0,0 +1,214 @@
+use geo::{HaversineDistance, LineLocatePoint};
+use gtfs_structures::{Shape, StopTime};
+
+//arrival and departure of one stop time, after filling in the ones the feed left empty
+pub struct ResolvedStopTime {
+    pub arrival_time: Option<u32>,
+    pub departure_time: Option<u32>,
+    pub interpolated: bool,
+}
+
+//how far along the trip each stop is, in whatever unit the source uses
+//only the ratios between stops matter, so feet, metres and degrees all work
+fn distances_along_trip(stop_times: &[StopTime], shape: Option<&Vec<Shape>>) -> Vec<Option<f64>> {
+    //shape_dist_traveled from the feed is the most accurate, but only when every stop has one and it never goes backwards
+    let feed_distances = stop_times
+        .iter()
+        .map(|stop_time| {
+            stop_time
+                .shape_dist_traveled
+                .map(|distance| distance as f64)
+        })
+        .collect::<Option<Vec<f64>>>();
+
+    if let Some(feed_distances) = feed_distances {
+        if feed_distances.windows(2).all(|pair| pair[0] <= pair[1]) {
+            return feed_distances.into_iter().map(Some).collect();
+        }
+    }
+
+    let stop_points = stop_times
+        .iter()
+        .map(
+            |stop_time| match (stop_time.stop.longitude, stop_time.stop.latitude) {
+                (Some(lon), Some(lat)) => Some(geo::Point::new(lon, lat)),
+                _ => None,
+            },
+        )
+        .collect::<Vec<Option<geo::Point>>>();
+
+    //project the stops onto the shape, a stop can't be before the one that came before it
+    if let Some(shape) = shape.filter(|shape| shape.len() >= 2) {
+        let linestring = geo::LineString::from(
+            shape
+                .iter()
+                .map(|point| (point.longitude, point.latitude))
+                .collect::<Vec<(f64, f64)>>(),
+        );
+
+        let mut previous = 0.;
+        return stop_points
+            .iter()
+            .map(|point| {
+                let fraction = linestring.line_locate_point(&(*point)?)?.max(previous);
+                previous = fraction;
+                Some(fraction)
+            })
+            .collect();
+    }
+
+    //no shape, straight lines between the stops
+    let mut total = 0.;
+    let mut previous_point: Option<geo::Point> = None;
+    stop_points
+        .iter()
+        .map(|point| {
+            let point = (*point)?;
+            if let Some(previous_point) = previous_point {
+                total = total + previous_point.haversine_distance(&point);
+            }
+            previous_point = Some(point);
+            Some(total)
+        })
+        .collect()
+}
+
+//fills arrival and departure times of stops between two timed stops, proportional to distance travelled
+//stops before the first or after the last timed stop can't be interpolated and stay empty
+pub fn interpolate_stop_times(
+    stop_times: &[StopTime],
+    shape: Option<&Vec<Shape>>,
+) -> Vec<ResolvedStopTime> {
+    //a stop with only one of the two times arrives and departs at the same moment
+    let mut resolved = stop_times
+        .iter()
+        .map(|stop_time| ResolvedStopTime {
+            arrival_time: stop_time.arrival_time.or(stop_time.departure_time),
+            departure_time: stop_time.departure_time.or(stop_time.arrival_time),
+            interpolated: false,
+        })
+        .collect::<Vec<ResolvedStopTime>>();
+
+    if resolved
+        .iter()
+        .all(|stop_time| stop_time.arrival_time.is_some())
+    {
+        return resolved;
+    }
+
+    let distances = distances_along_trip(stop_times, shape);
+
+    let timed_indexes = resolved
+        .iter()
+        .enumerate()
+        .filter(|(_, stop_time)| stop_time.arrival_time.is_some())
+        .map(|(index, _)| index)
+        .collect::<Vec<usize>>();
+
+    for pair in timed_indexes.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        if end - start < 2 {
+            continue;
+        }
+
+        let start_time = resolved[start].departure_time.unwrap() as f64;
+        let end_time = resolved[end].arrival_time.unwrap() as f64;
+
+        for index in (start + 1)..end {
+            let fraction = match (distances[start], distances[index], distances[end]) {
+                (Some(start_distance), Some(distance), Some(end_distance))
+                    if end_distance > start_distance =>
+                {
+                    ((distance - start_distance) / (end_distance - start_distance)).clamp(0., 1.)
+                }
+                //no usable distance, spread the stops evenly
+                _ => (index - start) as f64 / (end - start) as f64,
+            };
+
+            let time = (start_time + (end_time - start_time) * fraction).round() as u32;
+
+            resolved[index] = ResolvedStopTime {
+                arrival_time: Some(time),
+                departure_time: Some(time),
+                interpolated: true,
+            };
+        }
+    }
+
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    //a stop on the equator, one degree of longitude apart is the same distance everywhere on it
+    fn stop_time(longitude: f64, time: Option<u32>) -> StopTime {
+        StopTime {
+            arrival_time: time,
+            departure_time: time,
+            stop: Arc::new(gtfs_structures::Stop {
+                longitude: Some(longitude),
+                latitude: Some(0.),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fills_stops_between_timed_stops() {
+        let stop_times = vec![
+            stop_time(0., Some(100)),
+            stop_time(1., None),
+            stop_time(4., Some(500)),
+        ];
+
+        let resolved = interpolate_stop_times(&stop_times, None);
+
+        assert_eq!(resolved[1].arrival_time, Some(200));
+        assert_eq!(resolved[1].departure_time, Some(200));
+        assert!(resolved[1].interpolated);
+        assert!(!resolved[0].interpolated);
+        assert!(!resolved[2].interpolated);
+    }
+
+    #[test]
+    fn leaves_leading_and_trailing_stops_without_times() {
+        let stop_times = vec![
+            stop_time(0., None),
+            stop_time(1., Some(100)),
+            stop_time(2., None),
+            stop_time(3., Some(300)),
+            stop_time(4., None),
+        ];
+
+        let resolved = interpolate_stop_times(&stop_times, None);
+
+        //nothing before the first timed stop to interpolate from
+        assert_eq!(resolved[0].arrival_time, None);
+        assert_eq!(resolved[0].departure_time, None);
+        assert!(!resolved[0].interpolated);
+
+        assert_eq!(resolved[2].arrival_time, Some(200));
+        assert!(resolved[2].interpolated);
+
+        //and nothing after the last one
+        assert_eq!(resolved[4].arrival_time, None);
+        assert_eq!(resolved[4].departure_time, None);
+        assert!(!resolved[4].interpolated);
+    }
+
+    #[test]
+    fn one_time_is_used_for_both() {
+        let mut only_departure = stop_time(0., None);
+        only_departure.departure_time = Some(100);
+
+        let resolved = interpolate_stop_times(&[only_departure], None);
+
+        assert_eq!(resolved[0].arrival_time, Some(100));
+        assert_eq!(resolved[0].departure_time, Some(100));
+        assert!(!resolved[0].interpolated);
+    }
+}
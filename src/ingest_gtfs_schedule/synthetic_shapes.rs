@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::station_navigation;
+
+//a straight line through the stops of one stop pattern, for trips the feed gave no shape
+pub struct SyntheticShape {
+    pub shape_id: String,
+    pub route_id: String,
+    //(lon, lat) of each stop in stop_sequence order
+    pub points: Vec<(f64, f64)>,
+    pub trip_ids: Vec<String>,
+}
+
+//trips without a shape_id, or with one missing from shapes.txt, get one shape per route and distinct list of stops
+pub fn make_synthetic_shapes(gtfs: &gtfs_structures::Gtfs) -> Vec<SyntheticShape> {
+    //btreemap so the shape ids come out the same on every import
+    let mut patterns: BTreeMap<(String, Vec<String>), Vec<String>> = BTreeMap::new();
+
+    for (trip_id, trip) in &gtfs.trips {
+        let has_shape = trip
+            .shape_id
+            .as_ref()
+            .map_or(false, |shape_id| gtfs.shapes.contains_key(shape_id));
+
+        if has_shape {
+            continue;
+        }
+
+        let stop_ids = trip
+            .stop_times
+            .iter()
+            .map(|stoptime| stoptime.stop.id.to_owned())
+            .collect::<Vec<String>>();
+
+        patterns
+            .entry((trip.route_id.to_owned(), stop_ids))
+            .or_insert(vec![])
+            .push(trip_id.to_owned());
+    }
+
+    let mut patterns_per_route: HashMap<String, usize> = HashMap::new();
+
+    patterns
+        .into_iter()
+        .filter_map(|((route_id, stop_ids), mut trip_ids)| {
+            let mut points: Vec<(f64, f64)> = vec![];
+            for stop_id in &stop_ids {
+                let point = gtfs
+                    .stops
+                    .get(stop_id)
+                    .and_then(|stop| station_navigation::stop_or_parent_coordinates(gtfs, stop));
+
+                //a stop and its platform can share coordinates, repeated points make zero length segments
+                if let Some(point) = point {
+                    if points.last() != Some(&point) {
+                        points.push(point);
+                    }
+                }
+            }
+
+            if points.len() < 2 {
+                return None;
+            }
+
+            let count = patterns_per_route.entry(route_id.to_owned()).or_insert(0);
+            *count = *count + 1;
+
+            trip_ids.sort();
+
+            Some(SyntheticShape {
+                shape_id: format!("synthetic_{}_{}", route_id, count),
+                route_id: route_id,
+                points: points,
+                trip_ids: trip_ids,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use tokio_postgres::NoTls;
+
+//only the file format and the route conditions are needed here, picking colours is left to the importer
+#[allow(dead_code)]
+mod colour_overrides;
+
+//lists overrides in colour_overrides.json that no longer match anything in the loaded feeds
+#[tokio::main]
+async fn main() {
+    let postgresstring = arguments::parse(std::env::args())
+        .unwrap()
+        .get::<String>("postgres");
+
+    let postgresstring = match postgresstring {
+        Some(s) => s,
+        None => {
+            panic!("You need a postgres string");
+        }
+    };
+
+    let colouroverrides = arguments::parse(std::env::args())
+        .unwrap()
+        .get::<String>("colouroverrides")
+        .unwrap_or_else(|| String::from(colour_overrides::COLOUR_OVERRIDES_PATH));
+
+    let schemaname = arguments::parse(std::env::args())
+        .unwrap()
+        .get::<String>("schema")
+        .unwrap_or_else(|| String::from("gtfs"));
+
+    let colour_overrides = match colour_overrides::read_colour_overrides(&colouroverrides) {
+        Ok(colour_overrides) => colour_overrides,
+        Err(err) => panic!("Could not read colour overrides: {}", err),
+    };
+
+    // Connect to the database.
+    let (client, connection) = tokio_postgres::connect(&postgresstring, NoTls)
+        .await
+        .unwrap();
+
+    // The connection object performs the actual communication with the database,
+    // so spawn it off to run on its own.
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+
+    println!("Connected to database");
+
+    //feed id -> (route_id, route_type)
+    let mut routes_per_feed: HashMap<String, Vec<(String, i16)>> = HashMap::new();
+
+    for row in client
+        .query(
+            format!("SELECT onestop_feed_id, route_id, route_type FROM {schemaname}.routes;")
+                .as_str(),
+            &[],
+        )
+        .await
+        .unwrap()
+    {
+        routes_per_feed
+            .entry(row.get(0))
+            .or_insert(vec![])
+            .push((row.get(1), row.get(2)));
+    }
+
+    let mut stale = 0;
+
+    for (index, colour_override) in colour_overrides.overrides.iter().enumerate() {
+        if let Some(feed_id) = &colour_override.feed_id {
+            if !routes_per_feed.contains_key(feed_id) {
+                println!("override {}: feed {} is not loaded", index, feed_id);
+                stale = stale + 1;
+                continue;
+            }
+        }
+
+        //when_color is compared against the colour in the feed, which isn't stored, so it can't be checked here
+        let matches_a_route = routes_per_feed.iter().any(|(feed_id, routes)| {
+            routes.iter().any(|(route_id, route_type)| {
+                colour_override.matches_route(feed_id, route_id, *route_type)
+            })
+        });
+
+        if !matches_a_route {
+            println!(
+                "override {}: no route in {} matches route_id {:?}, route_id_pattern {:?}, route_type {:?}",
+                index,
+                colour_override.feed_id.as_deref().unwrap_or("any feed"),
+                colour_override.route_id,
+                colour_override.route_id_pattern,
+                colour_override.route_type
+            );
+            stale = stale + 1;
+        }
+    }
+
+    println!(
+        "{} of {} colour overrides are stale",
+        stale,
+        colour_overrides.overrides.len()
+    );
+
+    if stale > 0 {
+        std::process::exit(1);
+    }
+}